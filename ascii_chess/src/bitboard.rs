@@ -0,0 +1,494 @@
+use crate::board::{Colour, Matrix, Move, Piece};
+use crate::zobrist;
+
+//the position as one 64 bit mask per piece type and per colour, bit 0 is a1, bit 7 h1
+//and bit 63 h8. this is the move generator, not the board: the game keeps its
+//Matrix and converts to this once per move list, and perft and the search
+//convert once at the root and then only play moves on the bitboards
+#[derive(Clone, Copy)]
+pub struct Position {
+    pieces: [u64; 6],  //pawns, knights, bishops, rooks, queens, kings
+    colours: [u64; 2], //white then black
+    turn: usize,
+    castling: [[Option<usize>; 2]; 2], //same as Matrix::castling
+    chess960: bool,                    //castling is written as the king taking its rook
+    en_passant: Option<usize>,
+    halfmove: u32,
+    fullmove: u32,
+    key: u64, //zobrist key kept up to date by make_move, without the en passant part
+}
+
+pub const PAWN: usize = 0;
+pub const KNIGHT: usize = 1;
+pub const BISHOP: usize = 2;
+pub const ROOK: usize = 3;
+pub const QUEEN: usize = 4;
+pub const KING: usize = 5;
+pub const PIECES: [Piece; 6] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+];
+
+pub const WHITE: usize = 0;
+pub const BLACK: usize = 1;
+
+//rows and columns a ray goes each step: north, north east, east, south east,
+//south, south west, west, north west. the first four go up the bits
+const DIRECTIONS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+const DIAGONAL_RAYS: [usize; 4] = [1, 3, 5, 7];
+const STRAIGHT_RAYS: [usize; 4] = [0, 2, 4, 6];
+
+//every square each piece attacks on an empty board, worked out when compiling
+static KNIGHT_ATTACKS: [u64; 64] = step_table(&[
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+]);
+static KING_ATTACKS: [u64; 64] = step_table(&[
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+]);
+static PAWN_ATTACKS: [[u64; 64]; 2] = [
+    step_table(&[(1, -1), (1, 1)]),
+    step_table(&[(-1, -1), (-1, 1)]),
+];
+static RAYS: [[u64; 64]; 8] = ray_tables();
+
+const fn step_table(offsets: &[(i32, i32)]) -> [u64; 64] {
+    let mut table = [0; 64];
+    let mut square = 0;
+    while square < 64 {
+        let mut index = 0;
+        while index < offsets.len() {
+            let row = square as i32 / 8 + offsets[index].0;
+            let column = square as i32 % 8 + offsets[index].1;
+            if row >= 0 && row < 8 && column >= 0 && column < 8 {
+                table[square] |= 1 << (row * 8 + column);
+            }
+            index += 1;
+        }
+        square += 1;
+    }
+    table
+}
+
+const fn ray_tables() -> [[u64; 64]; 8] {
+    let mut tables = [[0; 64]; 8];
+    let mut direction = 0;
+    while direction < 8 {
+        let mut square = 0;
+        while square < 64 {
+            let mut row = square as i32 / 8 + DIRECTIONS[direction].0;
+            let mut column = square as i32 % 8 + DIRECTIONS[direction].1;
+            while row >= 0 && row < 8 && column >= 0 && column < 8 {
+                tables[direction][square] |= 1 << (row * 8 + column);
+                row += DIRECTIONS[direction].0;
+                column += DIRECTIONS[direction].1;
+            }
+            square += 1;
+        }
+        direction += 1;
+    }
+    tables
+}
+
+//squares a slider reaches along the given rays, stopping at (and including) the
+//first piece in the way
+fn slide(square: usize, occupied: u64, rays: &[usize; 4]) -> u64 {
+    let mut attacks = 0;
+    for &direction in rays {
+        let ray = RAYS[direction][square];
+        let blockers = ray & occupied;
+        if blockers == 0 {
+            attacks |= ray;
+            continue;
+        }
+        let first = if direction <= 2 || direction == 7 {
+            blockers.trailing_zeros() as usize
+        } else {
+            63 - blockers.leading_zeros() as usize
+        };
+        attacks |= ray ^ RAYS[direction][first];
+    }
+    attacks
+}
+
+fn bishop_attacks(square: usize, occupied: u64) -> u64 {
+    slide(square, occupied, &DIAGONAL_RAYS)
+}
+
+fn rook_attacks(square: usize, occupied: u64) -> u64 {
+    slide(square, occupied, &STRAIGHT_RAYS)
+}
+
+pub fn square_of(coords: (usize, usize)) -> usize {
+    coords.0 * 8 + coords.1
+}
+
+pub fn coords_of(square: usize) -> (usize, usize) {
+    (square / 8, square % 8)
+}
+
+//pops squares off a mask lowest first
+pub fn squares(mut mask: u64) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+        let square = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(square)
+    })
+}
+
+impl From<&Matrix> for Position {
+    fn from(matrix: &Matrix) -> Position {
+        let mut position = Position {
+            pieces: [0; 6],
+            colours: [0; 2],
+            turn: (matrix.turn == Colour::Black) as usize,
+            castling: matrix.castling,
+            chess960: matrix.chess960,
+            en_passant: matrix.en_passant.map(square_of),
+            halfmove: matrix.halfmove,
+            fullmove: matrix.fullmove,
+            key: 0,
+        };
+        for square in 0..64 {
+            let (piece, colour) = matrix.piece_at(coords_of(square));
+            if let Some(kind) = PIECES.iter().position(|candidate| *candidate == piece) {
+                position.toggle(kind, (colour == Colour::Black) as usize, square);
+            }
+        }
+        position.key ^= position.castling_key();
+        if position.turn == WHITE {
+            position.key ^= zobrist::white_to_move();
+        }
+        position
+    }
+}
+
+impl Position {
+    //the zobrist key of the position. en passant only counts when the side to move
+    //has a pawn that could take, so the same position always gets the same key
+    pub fn hash(&self) -> u64 {
+        match self.en_passant {
+            Some(square)
+                if PAWN_ATTACKS[self.turn ^ 1][square]
+                    & self.pieces[PAWN]
+                    & self.colours[self.turn]
+                    != 0 =>
+            {
+                self.key ^ zobrist::en_passant(square % 8)
+            }
+            _ => self.key,
+        }
+    }
+
+    //puts a piece on or takes it off a square, keeping the key in step
+    fn toggle(&mut self, kind: usize, side: usize, square: usize) {
+        self.pieces[kind] ^= 1 << square;
+        self.colours[side] ^= 1 << square;
+        self.key ^= zobrist::piece(kind, side == WHITE, square);
+    }
+
+    fn castling_key(&self) -> u64 {
+        let mut key = 0;
+        for (side, rights) in self.castling.iter().enumerate() {
+            for (wing, right) in rights.iter().enumerate() {
+                if right.is_some() {
+                    key ^= zobrist::castling(side, wing);
+                }
+            }
+        }
+        key
+    }
+
+    //the pieces of one kind and side
+    pub fn mask(&self, kind: usize, side: usize) -> u64 {
+        self.pieces[kind] & self.colours[side]
+    }
+
+    //WHITE or BLACK
+    pub fn turn(&self) -> usize {
+        self.turn
+    }
+
+    //true if the side to move is in check
+    pub fn checked(&self) -> bool {
+        self.in_check(self.turn)
+    }
+
+    pub fn occupied(&self) -> u64 {
+        self.colours[WHITE] | self.colours[BLACK]
+    }
+
+    pub fn kind_at(&self, square: usize) -> Option<usize> {
+        let bit = 1 << square;
+        (0..6).find(|kind| self.pieces[*kind] & bit != 0)
+    }
+
+    //true if the move takes something, en passant included
+    pub fn is_capture(&self, candidate: Move) -> bool {
+        let to = square_of(candidate.to);
+        self.colours[self.turn ^ 1] & (1 << to) != 0
+            || (self.kind_at(square_of(candidate.from)) == Some(PAWN)
+                && candidate.from.1 != candidate.to.1)
+    }
+
+    //true if any piece of `side` could take on the square
+    fn attacked(&self, square: usize, side: usize) -> bool {
+        let theirs = self.colours[side];
+        let occupied = self.occupied();
+        let diagonal = (self.pieces[BISHOP] | self.pieces[QUEEN]) & theirs;
+        let straight = (self.pieces[ROOK] | self.pieces[QUEEN]) & theirs;
+        //a pawn attacks the square from where a pawn of the other side on it would attack
+        PAWN_ATTACKS[side ^ 1][square] & self.pieces[PAWN] & theirs != 0
+            || KNIGHT_ATTACKS[square] & self.pieces[KNIGHT] & theirs != 0
+            || KING_ATTACKS[square] & self.pieces[KING] & theirs != 0
+            || bishop_attacks(square, occupied) & diagonal != 0
+            || rook_attacks(square, occupied) & straight != 0
+    }
+
+    fn in_check(&self, side: usize) -> bool {
+        let king = self.pieces[KING] & self.colours[side];
+        king != 0 && self.attacked(king.trailing_zeros() as usize, side ^ 1)
+    }
+
+    //same moves as Matrix's own generator, in no particular order
+    pub fn legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::with_capacity(64);
+        self.pseudo_legal_moves(&mut moves);
+        let mover = self.turn;
+        moves.retain(|candidate| {
+            let mut after = *self;
+            after.make_move(*candidate);
+            !after.in_check(mover)
+        });
+        moves
+    }
+
+    fn pseudo_legal_moves(&self, moves: &mut Vec<Move>) {
+        let ours = self.colours[self.turn];
+        let theirs = self.colours[self.turn ^ 1];
+        let occupied = ours | theirs;
+        let add = |moves: &mut Vec<Move>, from: usize, targets: u64| {
+            for to in squares(targets) {
+                moves.push(Move::new(coords_of(from), coords_of(to)));
+            }
+        };
+
+        self.pawn_moves(moves);
+        for from in squares(self.pieces[KNIGHT] & ours) {
+            add(moves, from, KNIGHT_ATTACKS[from] & !ours);
+        }
+        for from in squares((self.pieces[BISHOP] | self.pieces[QUEEN]) & ours) {
+            add(moves, from, bishop_attacks(from, occupied) & !ours);
+        }
+        for from in squares((self.pieces[ROOK] | self.pieces[QUEEN]) & ours) {
+            add(moves, from, rook_attacks(from, occupied) & !ours);
+        }
+        for from in squares(self.pieces[KING] & ours) {
+            add(moves, from, KING_ATTACKS[from] & !ours);
+            self.castling_moves(from, moves);
+        }
+    }
+
+    fn pawn_moves(&self, moves: &mut Vec<Move>) {
+        let ours = self.colours[self.turn];
+        let theirs = self.colours[self.turn ^ 1];
+        let empty = !(ours | theirs);
+        let (forward, start_row, last_row): (i32, usize, usize) = if self.turn == WHITE {
+            (8, 1, 7)
+        } else {
+            (-8, 6, 0)
+        };
+        let en_passant = self.en_passant.map_or(0, |square| 1 << square);
+        let add = |moves: &mut Vec<Move>, from: usize, to: usize| {
+            if to / 8 == last_row {
+                for promotion in [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight] {
+                    moves.push(Move::promote(coords_of(from), coords_of(to), promotion));
+                }
+            } else {
+                moves.push(Move::new(coords_of(from), coords_of(to)));
+            }
+        };
+
+        for from in squares(self.pieces[PAWN] & ours) {
+            let one = (from as i32 + forward) as usize;
+            if empty & (1 << one) != 0 {
+                add(moves, from, one);
+                let two = (one as i32 + forward) as usize;
+                if from / 8 == start_row && empty & (1 << two) != 0 {
+                    add(moves, from, two);
+                }
+            }
+            for to in squares(PAWN_ATTACKS[self.turn][from] & (theirs | en_passant)) {
+                add(moves, from, to);
+            }
+        }
+    }
+
+    //same rules as Matrix::castling_moves: king to the g or c file, rook next to
+    //it, nothing in the way and the king never on an attacked square
+    fn castling_moves(&self, from: usize, moves: &mut Vec<Move>) {
+        let home_row = if self.turn == WHITE { 0 } else { 7 };
+        if from / 8 != home_row {
+            return;
+        }
+        let ours = self.colours[self.turn];
+        let occupied = self.occupied();
+        for (wing, rook_column) in self.castling[self.turn].iter().enumerate() {
+            let rook_column = match rook_column {
+                Some(column) => *column,
+                None => continue,
+            };
+            let rook = home_row * 8 + rook_column;
+            if self.pieces[ROOK] & ours & (1 << rook) == 0 {
+                continue;
+            }
+            let (king_to, rook_to) = [(6, 5), (2, 3)][wing];
+            let span = |start: usize, end: usize| {
+                let (low, high) = (start.min(end), start.max(end));
+                (((1u64 << (high - low + 1)) - 1) << low) << (home_row * 8)
+            };
+            let king_path = span(from % 8, king_to);
+            let needed = (king_path | span(rook_column, rook_to)) & !(1 << from) & !(1 << rook);
+            if occupied & needed != 0 {
+                continue;
+            }
+            if squares(king_path).any(|square| self.attacked(square, self.turn ^ 1)) {
+                continue;
+            }
+            if self.chess960 {
+                moves.push(Move::new(coords_of(from), coords_of(rook)));
+            } else {
+                moves.push(Move::new(coords_of(from), (home_row, king_to)));
+            }
+        }
+    }
+
+    //plays a move that is assumed to be legal, the same way Matrix::make_move does
+    pub fn make_move(&mut self, chosen: Move) {
+        let from = square_of(chosen.from);
+        let to = square_of(chosen.to);
+        let us = self.turn;
+        let them = us ^ 1;
+        let kind = match self.kind_at(from) {
+            Some(kind) => kind,
+            None => return,
+        };
+        let castle = if kind != KING || from / 8 != to / 8 {
+            None
+        } else if self.chess960 {
+            (0..2).find(|wing| {
+                self.castling[us][*wing] == Some(to % 8) && self.colours[us] & (1 << to) != 0
+            })
+        } else if (from % 8).abs_diff(to % 8) == 2 {
+            Some(if to % 8 > from % 8 { 0 } else { 1 })
+        } else {
+            None
+        };
+        let captured = if castle.is_some() {
+            None
+        } else {
+            self.kind_at(to)
+        };
+
+        if let Some(captured) = captured {
+            self.toggle(captured, them, to);
+        }
+        if kind == PAWN && from % 8 != to % 8 && captured.is_none() {
+            //en passant, the pawn taken is beside the one taking it
+            self.toggle(PAWN, them, (from / 8) * 8 + to % 8);
+        }
+        let rook_column = castle.and_then(|wing| self.castling[us][wing]);
+        if let (Some(wing), Some(rook_column)) = (castle, rook_column) {
+            let row = from / 8 * 8;
+            let (king_to, rook_to) = [(6, 5), (2, 3)][wing];
+            self.toggle(ROOK, us, row + rook_column);
+            self.toggle(KING, us, from);
+            self.toggle(KING, us, row + king_to);
+            self.toggle(ROOK, us, row + rook_to);
+        } else {
+            self.toggle(kind, us, from);
+            let promoted = chosen
+                .promotion
+                .and_then(|promotion| PIECES.iter().position(|piece| *piece == promotion));
+            self.toggle(promoted.unwrap_or(kind), us, to);
+        }
+
+        self.key ^= self.castling_key();
+        if kind == KING {
+            self.castling[us] = [None, None];
+        }
+        for (rights, home_row) in self.castling.iter_mut().zip([0, 7]) {
+            for right in rights.iter_mut() {
+                if let Some(column) = *right {
+                    let square = home_row * 8 + column;
+                    if from == square || to == square {
+                        *right = None;
+                    }
+                }
+            }
+        }
+
+        self.key ^= self.castling_key();
+
+        self.en_passant = None;
+        if kind == PAWN && from.abs_diff(to) == 16 {
+            self.en_passant = Some((from + to) / 2);
+        }
+        if kind == PAWN || captured.is_some() {
+            self.halfmove = 0;
+        } else {
+            self.halfmove += 1;
+        }
+        if us == BLACK {
+            self.fullmove += 1;
+        }
+        self.turn = them;
+        self.key ^= zobrist::white_to_move();
+    }
+
+    pub fn perft(&self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for candidate in moves {
+            let mut after = *self;
+            after.make_move(candidate);
+            nodes += after.perft(depth - 1);
+        }
+        nodes
+    }
+}
@@ -1,22 +1,95 @@
+use crate::bitboard::Position;
+use crate::layout::BoardArea;
+use crate::pieces::{self, PieceRules};
+use crate::terminal::{Frame, Style};
+use crate::theme::{Theme, CELL_WIDTH};
+use crate::variants::{self, Rules, Variant};
+use crate::zobrist;
+use std::fmt;
+
+//the most rows or columns a variant's board can have
+pub const MAX_SIZE: usize = 12;
+
+#[derive(Clone)]
 pub struct Matrix {
-    pub board: [[BlockData; 8]; 8],
+    //only the first variant.rows rows and variant.columns columns are used
+    pub board: [[BlockData; MAX_SIZE]; MAX_SIZE],
+    pub variant: &'static Variant,
     pub arrow: (usize, usize),
     pub selected_piece: (usize, usize),
-    pub turn: Colour
+    pub dropping: Option<Piece>, //the crazyhouse pocket piece picked to drop
+    pub turn: Colour,
+    pub flipped: bool, //draws the board upside down, black at the top
+    //column of the rook each side can still castle with, king side then queen side
+    pub castling: [[Option<usize>; 2]; 2],
+    pub en_passant: Option<(usize, usize)>, //square a pawn skipped over last move
+    pub halfmove: u32,                      //moves since the last capture or pawn move
+    pub fullmove: u32,
+    //chess960 rules: castling is the king moving onto its own rook, which can start
+    //anywhere, and FENs name the rook's file when K or Q would be ambiguous
+    pub chess960: bool,
+    pub checks: [u8; 2], //checks given by white and black, for three-check
+    //crazyhouse: how many of each of the POCKET pieces white and black have to
+    //drop, and which pieces are promoted pawns, which go back to being pawns
+    //in the pocket of whoever takes them
+    pub pockets: [[u8; 5]; 2],
+    pub promoted: [[bool; MAX_SIZE]; MAX_SIZE],
+    key: u64, //zobrist key kept up to date by make_move, without the en passant part
+}
+
+//what make_move changed, so unmake_move can put it back. squares are listed
+//in the order they were changed
+pub struct Undo {
+    squares: Vec<((usize, usize), BlockData)>,
+    promoted: Vec<((usize, usize), bool)>,
+    castling: [[Option<usize>; 2]; 2],
+    en_passant: Option<(usize, usize)>,
+    halfmove: u32,
+    fullmove: u32,
+    checks: [u8; 2],
+    pockets: [[u8; 5]; 2],
+    key: u64,
 }
 
+//the pieces that can be in a crazyhouse pocket, in the order they are listed
+pub const POCKET: [Piece; 5] = [
+    Piece::Queen,
+    Piece::Rook,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Pawn,
+];
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct BlockData {
     colour: Colour,
     piece: Piece,
 }
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Colour {
     Black,
     White,
     Null,
 }
-#[derive(Clone, Copy, PartialEq)]
+impl Colour {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colour::Black => "Black",
+            Colour::White => "White",
+            Colour::Null => "Nobody",
+        }
+    }
+
+    pub fn opponent(&self) -> Colour {
+        match self {
+            Colour::Black => Colour::White,
+            Colour::White => Colour::Black,
+            Colour::Null => Colour::Null,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
 pub enum Piece {
     Pawn,
     Rook,
@@ -24,506 +97,2145 @@ pub enum Piece {
     Knight,
     Queen,
     King,
+    Archbishop,
+    Chancellor,
+    Amazon,
+    Camel,
+    Nightrider,
     Null,
 }
-
 impl Piece {
-    fn to_string(&self, colour: Colour) -> &str {
+    //lowercase letter used in saved positions, '.' for no piece
+    pub fn letter(&self) -> char {
         match self {
-            Piece::Pawn => {
-                return if Colour::Black == colour {
-                    "♟ "
-                } else {
-                    "♙ "
-                }
-            }
-            Piece::Rook => {
-                return if Colour::Black == colour {
-                    "♜ "
-                } else {
-                    "♖ "
-                }
-            }
-            Piece::Bishop => {
-                return if Colour::Black == colour {
-                    "♝ "
+            Piece::Pawn => 'p',
+            Piece::Rook => 'r',
+            Piece::Bishop => 'b',
+            Piece::Knight => 'n',
+            Piece::Queen => 'q',
+            Piece::King => 'k',
+            Piece::Archbishop => 'a',
+            Piece::Chancellor => 'c',
+            Piece::Amazon => 'm',
+            Piece::Camel => 'l',
+            Piece::Nightrider => 'i',
+            Piece::Null => '.',
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Piece::Pawn => "pawn",
+            Piece::Rook => "rook",
+            Piece::Bishop => "bishop",
+            Piece::Knight => "knight",
+            Piece::Queen => "queen",
+            Piece::King => "king",
+            Piece::Archbishop => "archbishop",
+            Piece::Chancellor => "chancellor",
+            Piece::Amazon => "amazon",
+            Piece::Camel => "camel",
+            Piece::Nightrider => "nightrider",
+            Piece::Null => "nothing",
+        }
+    }
+
+    pub fn from_letter(letter: char) -> Option<Piece> {
+        match letter.to_ascii_lowercase() {
+            'p' => Some(Piece::Pawn),
+            'r' => Some(Piece::Rook),
+            'b' => Some(Piece::Bishop),
+            'n' => Some(Piece::Knight),
+            'q' => Some(Piece::Queen),
+            'k' => Some(Piece::King),
+            'a' => Some(Piece::Archbishop),
+            'c' => Some(Piece::Chancellor),
+            'm' => Some(Piece::Amazon),
+            'l' => Some(Piece::Camel),
+            'i' => Some(Piece::Nightrider),
+            _ => None,
+        }
+    }
+}
+
+//the piece's name, like the status line uses it
+impl fmt::Display for Piece {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+//why a move was turned down, shown to the player so they know what was wrong
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MoveError {
+    NoPieceSelected,
+    NotYourTurn(Colour), //whose turn it actually is
+    SameSquare,
+    OwnPieceOnTarget,
+    PathBlocked,
+    NothingToTake, //a pawn moving diagonally onto an empty square
+    IllegalPieceMove(Piece),
+    CastlingNotAllowed,
+    CastlesThroughCheck,
+    LeavesKingInCheck,
+    MustCapture,    //antichess, there is something to take
+    SquareTaken,    //dropping onto a square with something on it
+    PawnOnEdgeRank, //dropping a pawn on the first or last rank
+}
+
+impl fmt::Display for MoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MoveError::NoPieceSelected => write!(f, "there is no piece on that square"),
+            MoveError::NotYourTurn(turn) => write!(f, "it is {}'s turn", turn.name()),
+            MoveError::SameSquare => write!(f, "the piece is already there"),
+            MoveError::OwnPieceOnTarget => write!(f, "you can't take your own piece"),
+            MoveError::PathBlocked => write!(f, "something is in the way"),
+            MoveError::NothingToTake => write!(f, "pawns only move diagonally to take"),
+            MoveError::IllegalPieceMove(piece) => {
+                let article = if piece.name().starts_with(['a', 'e', 'i', 'o', 'u']) {
+                    "an"
                 } else {
-                    "♗ "
-                }
+                    "a"
+                };
+                write!(f, "{} {} doesn't move like that", article, piece)
             }
-            Piece::Knight => {
-                return if Colour::Black == colour {
-                    "♞ "
-                } else {
-                    "♘ "
-                }
+            MoveError::CastlingNotAllowed => {
+                write!(f, "the king or that rook has moved, no castling that way")
             }
-            Piece::Queen => {
-                return if Colour::Black == colour {
-                    "♛ "
-                } else {
-                    "♕ "
-                }
+            MoveError::CastlesThroughCheck => {
+                write!(f, "the king can't castle out of, through or into check")
             }
-            Piece::King => {
-                return if Colour::Black == colour {
-                    "♚ "
-                } else {
-                    "♔ "
-                }
+            MoveError::LeavesKingInCheck => write!(f, "that would leave your king in check"),
+            MoveError::MustCapture => write!(f, "you have to take when you can"),
+            MoveError::SquareTaken => write!(f, "pieces can only be dropped on empty squares"),
+            MoveError::PawnOnEdgeRank => {
+                write!(f, "pawns can't be dropped on the first or last rank")
             }
+        }
+    }
+}
 
-            Piece::Null => {
-                return if Colour::Black == colour {
-                    "⬛"
-                } else {
-                    "⬜"
-                }
+//a move from one square to another, castling is written as the king's move.
+//a crazyhouse drop puts a piece from the pocket on `to`, `from` is the same square
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Move {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub promotion: Option<Piece>,
+    pub drop: Option<Piece>,
+}
+
+impl Move {
+    pub fn new(from: (usize, usize), to: (usize, usize)) -> Move {
+        Move {
+            from,
+            to,
+            promotion: None,
+            drop: None,
+        }
+    }
+
+    pub fn promote(from: (usize, usize), to: (usize, usize), piece: Piece) -> Move {
+        Move {
+            from,
+            to,
+            promotion: Some(piece),
+            drop: None,
+        }
+    }
+
+    pub fn drop(piece: Piece, to: (usize, usize)) -> Move {
+        Move {
+            from: to,
+            to,
+            promotion: None,
+            drop: Some(piece),
+        }
+    }
+
+    //long algebraic like e2e4 or e7e8q, used in saved games and by perft.
+    //drops are written N@f3
+    pub fn name(&self) -> String {
+        if let Some(piece) = self.drop {
+            return format!(
+                "{}@{}",
+                piece.letter().to_ascii_uppercase(),
+                square_name(self.to)
+            );
+        }
+        let mut name = format!("{}{}", square_name(self.from), square_name(self.to));
+        if let Some(piece) = self.promotion {
+            name.push(piece.letter());
+        }
+        name
+    }
+
+    pub fn parse(text: &str) -> Option<Move> {
+        if let Some((letter, square)) = text.split_once('@') {
+            let mut letters = letter.chars();
+            let piece = Piece::from_letter(letters.next()?)?;
+            if letters.next().is_some() {
+                return None;
             }
+            return Some(Move::drop(piece, parse_square(square)?));
         }
+        //squares on bigger boards can have two digit ranks, like a10
+        let (from, rest) = split_square(text)?;
+        let (to, rest) = split_square(rest)?;
+        let promotion = match rest {
+            "" => None,
+            letter if letter.len() == 1 => Some(Piece::from_letter(letter.chars().next()?)?),
+            _ => return None,
+        };
+        Some(Move {
+            from,
+            to,
+            promotion,
+            drop: None,
+        })
     }
 }
 
 impl Matrix {
     pub fn new() -> Matrix {
-        let mut board: [[BlockData; 8]; 8] = [[BlockData {
+        Matrix::empty(&variants::STANDARD)
+    }
+
+    //a board of the variant's size with nothing on it
+    pub fn empty(variant: &'static Variant) -> Matrix {
+        let mut board = [[BlockData {
             colour: Colour::Null,
             piece: Piece::Null,
-        }; 8]; 8];
-        for row in 0..8 {
-            for block in 0..8 {
+        }; MAX_SIZE]; MAX_SIZE];
+        for (row, blocks) in board.iter_mut().enumerate() {
+            for (block, data) in blocks.iter_mut().enumerate() {
                 //checkerboards the board, so that every odd row starts with white, and every even starts with black
-                board[row][block].colour = if row % 2 == 0 {
-                    if block % 2 == 0 {
-                        Colour::Black
-                    } else {
-                        Colour::White
-                    }
-                } else {
-                    if block % 2 == 0 {
-                        Colour::White
-                    } else {
-                        Colour::Black
-                    }
-                };
+                data.colour = square_shade((row, block));
             }
         }
 
-        Matrix {
-            board: board,
-            arrow: (7, 0),
-            selected_piece: (7, 0),
-            turn: Colour::White
+        let last_row = variant.rows - 1;
+        let rights = [Some(variant.columns - 1), Some(0)];
+        let mut matrix = Matrix {
+            board,
+            variant,
+            arrow: (last_row, 0),
+            selected_piece: (last_row, 0),
+            dropping: None,
+            turn: Colour::White,
+            flipped: false,
+            castling: if variant.castling {
+                [rights, rights]
+            } else {
+                [[None, None], [None, None]]
+            },
+            en_passant: None,
+            halfmove: 0,
+            fullmove: 1,
+            chess960: false,
+            checks: [0, 0],
+            pockets: [[0; 5]; 2],
+            promoted: [[false; MAX_SIZE]; MAX_SIZE],
+            key: 0,
+        };
+        matrix.key = matrix.fresh_key();
+        matrix
+    }
+
+    //moves a piece without checking anything, leaving its old square empty
+    fn switch_pieces(
+        &mut self,
+        piece_moved: (usize, usize),
+        spot: (usize, usize),
+        undo: &mut Undo,
+    ) {
+        let block = self.board[piece_moved.0][piece_moved.1];
+        self.place(spot, block, undo);
+        self.place(piece_moved, vacant(piece_moved), undo);
+    }
+
+    fn clear(&mut self, coords: (usize, usize)) {
+        self.put(coords, vacant(coords));
+    }
+
+    //puts a piece or an empty square on the board, keeping the key in step
+    fn put(&mut self, coords: (usize, usize), block: BlockData) {
+        self.key ^= self.piece_key(coords);
+        self.board[coords.0][coords.1] = block;
+        self.key ^= self.piece_key(coords);
+    }
+
+    //put for make_move, remembering what was there for unmake_move
+    fn place(&mut self, coords: (usize, usize), block: BlockData, undo: &mut Undo) {
+        undo.squares.push((coords, self.board[coords.0][coords.1]));
+        self.put(coords, block);
+    }
+
+    fn toggle_turn(&mut self) {
+        self.turn = self.turn.opponent();
+        self.key ^= zobrist::white_to_move();
+    }
+
+    //a pocket count changed by a capture or a drop, keeping the key in step
+    fn set_pocket(&mut self, side: usize, index: usize, count: u8) {
+        self.key ^= zobrist::pocket(side, index, self.pockets[side][index] as usize);
+        self.pockets[side][index] = count;
+        self.key ^= zobrist::pocket(side, index, count as usize);
+    }
+
+    //drops a piece from the pocket on the arrow if that is a legal drop. returns
+    //the move, or why it isn't allowed
+    pub fn drop_piece(&mut self, piece: Piece) -> Result<Move, MoveError> {
+        let chosen = Move::drop(piece, self.arrow);
+        if self.legal_moves().contains(&chosen) {
+            self.make_move(chosen);
+            return Ok(chosen);
+        }
+        if !self.is_empty(chosen.to) {
+            return Err(MoveError::SquareTaken);
         }
+        if piece.rules().pawn_like() && (chosen.to.0 == 0 || chosen.to.0 == self.rows() - 1) {
+            return Err(MoveError::PawnOnEdgeRank);
+        }
+        Err(MoveError::LeavesKingInCheck)
     }
 
-    fn switch_pieces(&mut self, piece_moved: (usize, usize), spot: (usize, usize)) {
-        self.board[spot.0][spot.1] = self.board[piece_moved.0][piece_moved.1];
-        //sets consistant tile color for checkerboard pattern
-        let new_colour: Colour = if piece_moved.0 % 2 == 0 {
-            if piece_moved.1 % 2 == 0 {
-                Colour::Black
-            } else {
-                Colour::White
-            }
-        } else {
-            if piece_moved.1 % 2 == 0 {
-                Colour::White
-            } else {
-                Colour::Black
-            }
+    //the next piece the side to move has in its pocket after `current`, in
+    //POCKET order. None after the last one, so picking goes round to nothing
+    pub fn next_in_pocket(&self, current: Option<Piece>) -> Option<Piece> {
+        let side = side_index(self.turn);
+        let start = match current {
+            Some(piece) => pocket_index(piece)? + 1,
+            None => 0,
         };
-        let new_blockdata = BlockData {
-            colour: new_colour,
-            piece: Piece::Null,
-        }; //defines new blockdata variable to set consistent tile colors
+        (start..POCKET.len())
+            .find(|index| self.pockets[side][*index] > 0)
+            .map(|index| POCKET[index])
+    }
 
-        self.board[piece_moved.0][piece_moved.1] = new_blockdata;
+    //moves the selected piece to the arrow if that is a legal move, pawns reaching
+    //the last rank become queens. returns the move, or why it isn't allowed
+    pub fn movement(&mut self, piece_coords: (usize, usize)) -> Result<Move, MoveError> {
+        let chosen = self.legal_moves().into_iter().find(|candidate| {
+            candidate.from == piece_coords
+                && candidate.to == self.arrow
+                && matches!(candidate.promotion, None | Some(Piece::Queen))
+        });
+        match chosen {
+            Some(chosen) => {
+                self.make_move(chosen);
+                Ok(chosen)
+            }
+            None => Err(self.rejection(piece_coords, self.arrow)),
+        }
     }
 
-    //checks if a piece is taking its teammate (false==taking its teammate, true==valid movement)
-    fn validate_friend(&self, piece_team: Colour, move_coords: (usize, usize)) -> bool {
-        let spot_info = self.board[move_coords.0][move_coords.1];
-        if spot_info.piece == Piece::Null {
-            //if the spot piece is jumping to is a tile
-            return true;
+    //works out why from -> to isn't one of the legal moves
+    fn rejection(&self, from: (usize, usize), to: (usize, usize)) -> MoveError {
+        let (piece, colour) = self.piece_at(from);
+        if piece == Piece::Null {
+            return MoveError::NoPieceSelected;
         }
-        if spot_info.colour == piece_team {
-            return false;
-        } else {
-            return true;
-        }
-    }
-
-    //returns false if piece hop is invalid, true if piece hop is valid.
-    //only use for bishop, rook, and queen
-    fn check_piece_hop(&self, piece_coords: (usize, usize), move_coords: (usize, usize)) -> bool {
-        //checks if piece is moving straight horizontally or vertically
-        if piece_coords.0 == move_coords.0 || piece_coords.1 == move_coords.1 {
-            let mut range: (usize, usize, bool) = (0, 0, false); //bool determines vertical or horizontal movement
-            if piece_coords.0 == move_coords.0 {
-                range.0 = piece_coords.1.min(move_coords.1);
-                range.1 = piece_coords.1.max(move_coords.1);
-                range.2 = false; //false for horizontal movement
-            } else if piece_coords.1 == move_coords.1 {
-                range.0 = piece_coords.0.min(move_coords.0);
-                range.1 = piece_coords.0.max(move_coords.0);
-                range.2 = true; //true for vertical movement
-            } else {
-                return false;
-            }
-            for index in range.0 + 1..range.1 {
-                let spot = self.board[if range.2 { index } else { piece_coords.0 }]
-                    [if range.2 { piece_coords.1 } else { index }];
-                if spot.piece != Piece::Null {
-                    return false;
-                }
-            }
-            return true;
+        if colour != self.turn && self.variant.rules == Rules::FogOfWar {
+            return MoveError::NoPieceSelected;
+        }
+        if colour != self.turn {
+            return MoveError::NotYourTurn(self.turn);
+        }
+        if from == to {
+            return MoveError::SameSquare;
+        }
+        if self.variant.rules == Rules::FogOfWar {
+            //anything more would give away what stands in the dark
+            return MoveError::IllegalPieceMove(piece);
+        }
+        if let Some(reason) = self.castling_rejection(from, to) {
+            return reason;
         }
-        //checks if piece is moving in a diagnol fashion
-        else if (piece_coords.0 + piece_coords.1) == (move_coords.0 + move_coords.1)
-            || (piece_coords.1 as i32 - piece_coords.0 as i32)
-                == (move_coords.1 as i32 - move_coords.0 as i32)
+        if self.is_friend(to, colour) {
+            return MoveError::OwnPieceOnTarget;
+        }
+        let pseudo_legal = self.pseudo_legal_moves();
+        if pseudo_legal
+            .iter()
+            .any(|candidate| candidate.from == from && candidate.to == to)
         {
-            let range_x: (usize, usize) = (
-                piece_coords.0.min(move_coords.0),
-                piece_coords.0.max(move_coords.0),
-            );
-            let range_y: (usize, usize) = (
-                if piece_coords.0 == range_x.0 {
-                    piece_coords.1
-                } else {
-                    move_coords.1
-                },
-                if piece_coords.0 == range_x.1 {
-                    piece_coords.1
+            if !self.variant.rules.royal_king() {
+                return MoveError::MustCapture;
+            }
+            return MoveError::LeavesKingInCheck;
+        }
+        let rules = piece.rules();
+        //seen from white's side like the rules are written
+        let rows = (to.0 as i32 - from.0 as i32) * forward(colour);
+        let columns = to.1 as i32 - from.1 as i32;
+        if rules.takes().contains(&(rows, columns)) {
+            return MoveError::NothingToTake;
+        }
+        //the piece could move that way on an empty board, so something is in the way
+        let shape_fits = rules.slides().iter().any(|direction| {
+            (1..MAX_SIZE as i32)
+                .any(|distance| (direction.0 * distance, direction.1 * distance) == (rows, columns))
+        }) || rules.pushes().iter().any(|step| {
+            (rows, columns) == *step
+                || (rules.pawn_like()
+                    && self.double_steps(from.0, colour)
+                    && (rows, columns) == (2 * step.0, 2 * step.1))
+        });
+        if shape_fits {
+            return MoveError::PathBlocked;
+        }
+        MoveError::IllegalPieceMove(piece)
+    }
+
+    //why a castling attempt didn't work, None if from -> to isn't castling at all
+    fn castling_rejection(&self, from: (usize, usize), to: (usize, usize)) -> Option<MoveError> {
+        let (piece, colour) = self.piece_at(from);
+        let side = side_index(colour);
+        let attempt = if self.chess960 {
+            self.piece_at(to) == (Piece::Rook, colour)
+        } else {
+            from.1.abs_diff(to.1) >= 2
+        };
+        if !piece.rules().royal() || from.0 != to.0 || !attempt {
+            return None;
+        }
+        let right = self
+            .castling_wing(from, to)
+            .map(|wing| (wing, self.castling[side][wing]));
+        Some(match right {
+            Some((wing, Some(rook_column))) if from.0 == self.home_row(colour) => {
+                if self.castling_path_clear(from, rook_column, wing) {
+                    MoveError::CastlesThroughCheck
                 } else {
-                    move_coords.1
-                },
-            );
-            let mut count = if range_y.0 < range_y.1 { 1 } else { -1 };
-            for index in range_x.0 + 1..range_x.1 {
-                let spot: BlockData = self.board[index][(range_y.0 as i32 + count) as usize];
-                count += if range_y.0 < range_y.1 { 1 } else { -1 };
-                if spot.piece != Piece::Null {
-                    return false;
+                    MoveError::PathBlocked
                 }
             }
-            return true;
+            _ => MoveError::CastlingNotAllowed,
+        })
+    }
+
+    //which wing a king move castles on, None for an ordinary move. the move is
+    //assumed to be legal, so only the way castling is written needs checking
+    pub fn castling_wing(&self, from: (usize, usize), to: (usize, usize)) -> Option<usize> {
+        let (piece, colour) = self.piece_at(from);
+        if !piece.rules().royal() || from.0 != to.0 {
+            return None;
+        }
+        if self.chess960 {
+            if self.piece_at(to) != (Piece::Rook, colour) {
+                return None;
+            }
+            let side = side_index(colour);
+            return (0..2).find(|wing| self.castling[side][*wing] == Some(to.1));
+        }
+        //the king's ordinary moves are one column at most, anything further is castling
+        if from.1.abs_diff(to.1) >= 2 {
+            return Some(if to.1 > from.1 { 0 } else { 1 });
+        }
+        None
+    }
+
+    pub fn rows(&self) -> usize {
+        self.variant.rows
+    }
+
+    pub fn columns(&self) -> usize {
+        self.variant.columns
+    }
+
+    //where a colour's back row is
+    fn home_row(&self, colour: Colour) -> usize {
+        if colour == Colour::Black {
+            self.rows() - 1
         } else {
-            return false;
+            0
+        }
+    }
+
+    //where a colour's pawns start, in front of the back row
+    fn pawn_row(&self, colour: Colour) -> usize {
+        if colour == Colour::Black {
+            self.rows() - 2
+        } else {
+            1
         }
     }
 
+    //pawns may double step from the row they start on, the horde's from the
+    //first row as well
+    fn double_steps(&self, row: usize, colour: Colour) -> bool {
+        let horde = self.variant.rules == Rules::Horde && colour == Colour::White;
+        self.variant.double_step
+            && (row == self.pawn_row(colour) || (horde && row == self.home_row(colour)))
+    }
 
-    fn toggle_turn(&mut self){
-        if self.turn == Colour::Black{
-            self.turn = Colour::White;
+    //where the king and rook end up, the king one square from the corner
+    //on the king side and on the c file on the queen side
+    fn castle_columns(&self, wing: usize) -> (usize, usize) {
+        if wing == 0 {
+            (self.columns() - 2, self.columns() - 3)
+        } else {
+            (2, 3)
         }
-        else if self.turn == Colour::White{
-            self.turn = Colour::Black;
+    }
+
+    //the square `displace` rows and columns away, None if that is off the board
+    fn offset(&self, coords: (usize, usize), displace: (i32, i32)) -> Option<(usize, usize)> {
+        let row = coords.0 as i32 + displace.0;
+        let column = coords.1 as i32 + displace.1;
+        if !(0..self.rows() as i32).contains(&row) || !(0..self.columns() as i32).contains(&column)
+        {
+            return None;
         }
-        else{
-            self.turn = Colour::Null;
+        Some((row as usize, column as usize))
+    }
+
+    //every square of the board, the first rank first
+    fn squares(&self) -> impl Iterator<Item = (usize, usize)> {
+        let columns = self.columns();
+        (0..self.rows()).flat_map(move |row| (0..columns).map(move |column| (row, column)))
+    }
+
+    pub fn piece_at(&self, coords: (usize, usize)) -> (Piece, Colour) {
+        let block = self.board[coords.0][coords.1];
+        if block.piece == Piece::Null {
+            return (Piece::Null, Colour::Null);
         }
+        (block.piece, block.colour)
     }
 
-    pub fn movement(&mut self, piece_coords: (usize, usize)) -> bool {
-        //returns true if movement was a success, false if piece failed to move
-        let piece_type = self.board[piece_coords.0][piece_coords.1];
-        //check if it is the selected piece's colors turn to go
-        if !(piece_type.colour == self.turn){
-            return false;
+    //every move the side to move can make without leaving its king in check,
+    //worked out on bitboards which is much faster than walking the array, when
+    //the bitboards can hold the position. the board is converted on every call,
+    //so loops that need many move lists should work on a Position themselves
+    pub fn legal_moves(&self) -> Vec<Move> {
+        if !self.fits_bitboards() {
+            return self.scan_legal_moves();
         }
+        Position::from(self).legal_moves()
+    }
 
-        match piece_type.piece {
-            Piece::Pawn => {
-                //validate if spot jumping to is occupied by friendly piece
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
-                }
-                //creates a list of 4 possible spots pawn can move
-                let mut valid_spots: [(i32, i32); 4] = [(0, 0); 4];
-                //typecasting piece and arrow coords to i32 because usize doesnt support negatives and i'm too lazy to create a custom exception
-                let temp_coords: (i32, i32) = (piece_coords.0 as i32, piece_coords.1 as i32);
-                let temp_arrow: (i32, i32) = (self.arrow.0 as i32, self.arrow.1 as i32);
-                //one spot straight ahead
-                valid_spots[0] = (
-                    if piece_type.colour == Colour::Black {
-                        temp_coords.0 - 1
-                    } else {
-                        temp_coords.0 + 1
-                    },
-                    temp_coords.1,
-                );
-                //two spots ahead
-                valid_spots[1] = (
-                    if piece_type.colour == Colour::Black {
-                        temp_coords.0 - 2
-                    } else {
-                        temp_coords.0 + 2
-                    },
-                    temp_coords.1,
-                );
-                //to the right and to the left, taking an enemy piece
-                valid_spots[2] = if piece_type.colour == Colour::Black {
-                    (temp_coords.0 - 1, temp_coords.1 + 1)
-                } else {
-                    (temp_coords.0 + 1, temp_coords.1 + 1)
-                };
-                valid_spots[3] = if piece_type.colour == Colour::Black {
-                    (temp_coords.0 - 1, temp_coords.1 - 1)
-                } else {
-                    (temp_coords.0 + 1, temp_coords.1 - 1)
-                };
+    //zobrist key identifying the position, the same key polyglot books use.
+    //fairy pieces and boards of other sizes get keys of their own, which only
+    //have to tell positions of this game apart.
+    //en passant only counts when a pawn could take, like on the bitboards
+    pub fn hash(&self) -> u64 {
+        if let Some(square) = self.en_passant {
+            let row = square.0 as i32 - forward(self.turn);
+            let takers = [-1, 1]
+                .iter()
+                .filter_map(|side| self.offset((row as usize, square.1), (0, *side)));
+            if takers
+                .into_iter()
+                .any(|taker| self.piece_at(taker) == (Piece::Pawn, self.turn))
+            {
+                return self.key ^ zobrist::en_passant(square.1 % 8);
+            }
+        }
+        self.key
+    }
 
-                //check if new spot is not an option in the valid_spots list
-                if !valid_spots.contains(&temp_arrow) {
-                    return false;
-                }
+    //the key worked out from scratch, which make_move keeps up to date after that
+    fn fresh_key(&self) -> u64 {
+        let mut key = self.castling_key();
+        for square in self.squares() {
+            key ^= self.piece_key(square);
+        }
+        if self.turn == Colour::White {
+            key ^= zobrist::white_to_move();
+        }
+        for (side, checks) in self.checks.iter().enumerate() {
+            key ^= zobrist::checks(side, *checks as usize);
+        }
+        for (side, pocket) in self.pockets.iter().enumerate() {
+            for (index, count) in pocket.iter().enumerate() {
+                key ^= zobrist::pocket(side, index, *count as usize);
+            }
+        }
+        key
+    }
 
-                //validate if there is an empty slot when moving diagnoly
-                if temp_arrow == valid_spots[2] || temp_arrow == valid_spots[3] {
-                    if self.board[self.arrow.0][self.arrow.1].piece == Piece::Null {
-                        return false;
-                    }
-                }
+    //polyglot's key for the usual pieces on variants the bitboards can play
+    fn piece_key(&self, coords: (usize, usize)) -> u64 {
+        let (piece, colour) = self.piece_at(coords);
+        let white = colour == Colour::White;
+        match pieces::ALL.iter().position(|known| *known == piece) {
+            Some(kind) if self.variant.bitboards && !pieces::FAIRY.contains(&piece) => {
+                zobrist::piece(kind, white, coords.0 * 8 + coords.1)
+            }
+            Some(kind) => zobrist::extra(kind, white, coords.0 * MAX_SIZE + coords.1),
+            None => 0,
+        }
+    }
 
-                //validate if there is a piece in front of the pawn when moving straight
-                if temp_arrow == valid_spots[0] {
-                    if self.board[self.arrow.0][self.arrow.1].piece != Piece::Null {
-                        return false;
-                    }
+    fn castling_key(&self) -> u64 {
+        let mut key = 0;
+        for (side, rights) in self.castling.iter().enumerate() {
+            for (wing, right) in rights.iter().enumerate() {
+                if right.is_some() {
+                    key ^= zobrist::castling(side, wing);
                 }
+            }
+        }
+        key
+    }
 
-                //validate conditions for jumping 2 spaces (no space in between, first pawn movement for instance)
-                if temp_arrow == valid_spots[1] {
-                    if piece_coords.0 != 6 && piece_type.colour == Colour::Black {
-                        return false;
-                    }
-                    if piece_coords.0 != 1 && piece_type.colour == Colour::White {
-                        return false;
-                    }
-                    if self.board[if Colour::Black == piece_type.colour {
-                        piece_coords.0 - 1
-                    } else {
-                        piece_coords.0 + 1
-                    }][piece_coords.1]
-                        .piece
-                        != Piece::Null
-                    {
-                        return false;
-                    }
-                }
+    //false for variants played on other boards or with other rules, and once
+    //there is a piece on the board the bitboards don't have a mask for.
+    //everything then goes through the array generator instead
+    pub fn fits_bitboards(&self) -> bool {
+        self.variant.bitboards
+            && self
+                .board
+                .iter()
+                .flatten()
+                .all(|block| !pieces::FAIRY.contains(&block.piece))
+    }
 
-                self.switch_pieces(piece_coords, self.arrow);
-                self.toggle_turn();
-                return true;
+    //the same moves found by walking the array square by square. slower, kept
+    //to check the bitboard generator against
+    pub fn scan_legal_moves(&self) -> Vec<Move> {
+        let mut moves = self.pseudo_legal_moves();
+        let mover = self.turn;
+        let kings = (self.king(mover), self.king(mover.opponent()));
+        if !self.variant.rules.royal_king() {
+            //nothing to keep safe, but in antichess taking is compulsory
+            let takes = |candidate: &Move| {
+                !self.is_empty(candidate.to)
+                    || (self.piece_at(candidate.from).0.rules().pawn_like()
+                        && candidate.from.1 != candidate.to.1)
+            };
+            if self.variant.rules == Rules::Antichess && moves.iter().any(takes) {
+                moves.retain(takes);
             }
-            Piece::Knight => {
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
-                }
-                let mut new_spots: [(usize, usize); 8] = [(0, 0); 8];
-                for index in 0..8 {
-                    let mut x_pos: i32 = 0;
-                    let mut y_pos: i32 = 0;
-                    if index % 2 == 0 {
-                        x_pos = if index > 3 { 2 } else { -2 };
-                    } else {
-                        y_pos = if index > 3 { 2 } else { -2 };
-                    }
-                    if 2 <= index && index <= 5 {
-                        if x_pos == 0 {
-                            x_pos = -1;
-                        } else if y_pos == 0 {
-                            y_pos = -1;
-                        }
-                    } else {
-                        if x_pos == 0 {
-                            x_pos = 1;
-                        } else if y_pos == 0 {
-                            y_pos = 1;
-                        }
-                    }
+            return moves;
+        }
+        let mut after = self.clone();
+        moves.retain(|candidate| {
+            let undo = after.make_move(*candidate);
+            //in atomic a king can be blown up, which is never allowed for your own
+            //and wins straight away for the other one. the horde never had one
+            let legal = match (after.king(mover), after.king(mover.opponent())) {
+                (None, _) => kings.0.is_none(),
+                (Some(_), None) if kings.1.is_some() => true,
+                _ => !after.in_check(mover),
+            };
+            after.unmake_move(undo);
+            legal
+        });
+        moves
+    }
+
+    //moves that follow how the pieces move but may leave the king in check.
+    //castling is only generated when the king doesn't pass through an attacked square
+    fn pseudo_legal_moves(&self) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for from in self.squares() {
+            let block = self.board[from.0][from.1];
+            if block.piece == Piece::Null || block.colour != self.turn {
+                continue;
+            }
+            let rules = block.piece.rules();
+            self.piece_moves(from, rules, &mut moves);
+            if rules.royal() && self.variant.rules == Rules::Atomic {
+                //the king would blow itself up
+                moves.retain(|candidate| candidate.from != from || self.is_empty(candidate.to));
+            }
+            if rules.royal() {
+                self.castling_moves(from, &mut moves);
+            }
+        }
+        if self.variant.rules == Rules::Crazyhouse {
+            self.drop_moves(&mut moves);
+        }
+        moves
+    }
 
-                    new_spots[index] = (
-                        (piece_coords.0 as i32 + x_pos) as usize,
-                        (piece_coords.1 as i32 + y_pos) as usize,
-                    );
+    //everything the rules of the piece on `from` allow, whether or not it
+    //leaves the king in check. pieces that promote do so on the last row, into
+    //whatever the variant allows
+    fn piece_moves(&self, from: (usize, usize), rules: &dyn PieceRules, moves: &mut Vec<Move>) {
+        let last_row = self.home_row(self.turn.opponent());
+        let mut add = |to: (usize, usize)| {
+            if to.0 == last_row && rules.promotes() {
+                for promotion in self.variant.promotions {
+                    moves.push(Move::promote(from, to, *promotion));
                 }
+            } else {
+                moves.push(Move::new(from, to));
+            }
+        };
 
-                if !new_spots.contains(&self.arrow) {
-                    return false;
-                } else {
-                    self.switch_pieces(piece_coords, self.arrow);
-                    self.toggle_turn();
-                    return true;
+        for displace in rules.leaps() {
+            if let Some(to) = self.offset(from, facing(*displace, self.turn)) {
+                if !self.is_friend(to, self.turn) {
+                    add(to);
                 }
             }
-            Piece::Rook => {
-                //cases in which rook should not move. Includes jumping to the same tile currently residing
-                if self.arrow == piece_coords {
-                    return false;
+        }
+        for direction in rules.slides() {
+            let direction = facing(*direction, self.turn);
+            let mut current = from;
+            while let Some(to) = self.offset(current, direction) {
+                if self.is_friend(to, self.turn) {
+                    break;
                 }
-                //taking friendly piece
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
+                add(to);
+                if !self.is_empty(to) {
+                    break;
                 }
-                //trying to move to a tile either not in the same x plane or y plane
-                if self.arrow.0 != piece_coords.0 && self.arrow.1 != piece_coords.1 {
-                    return false;
+                current = to;
+            }
+        }
+        for step in rules.pushes() {
+            let step = facing(*step, self.turn);
+            if let Some(one) = self.offset(from, step) {
+                if self.is_empty(one) {
+                    add(one);
+                    if let Some(two) = self.offset(one, step) {
+                        if rules.pawn_like()
+                            && self.double_steps(from.0, self.turn)
+                            && self.is_empty(two)
+                        {
+                            add(two);
+                        }
+                    }
                 }
-                //invalidating false jumps over extra pieces
-                if !self.check_piece_hop(piece_coords, self.arrow) {
-                    return false;
+            }
+        }
+        for step in rules.takes() {
+            if let Some(to) = self.offset(from, facing(*step, self.turn)) {
+                let target = self.board[to.0][to.1];
+                let capture = target.piece != Piece::Null && target.colour != self.turn;
+                if capture || (rules.pawn_like() && self.en_passant == Some(to)) {
+                    add(to);
                 }
+            }
+        }
+    }
+
+    //the king ends on the g or c file (the i file on 10 columns) and the rook next
+    //to it on the inside. every square either of them crosses has to be empty, and
+    //the king can't castle out of, through or into check
+    fn castling_moves(&self, from: (usize, usize), moves: &mut Vec<Move>) {
+        let side = side_index(self.turn);
+        let enemy = self.turn.opponent();
+        if from.0 != self.home_row(self.turn) || !self.variant.castling {
+            return;
+        }
+        for (wing, rook_column) in self.castling[side].iter().enumerate() {
+            let rook_column = match rook_column {
+                Some(column) => *column,
+                None => continue,
+            };
+            let rook = (from.0, rook_column);
+            if self.board[rook.0][rook.1]
+                != (BlockData {
+                    colour: self.turn,
+                    piece: Piece::Rook,
+                })
+            {
+                continue;
+            }
+            if !self.castling_path_clear(from, rook_column, wing) {
+                continue;
+            }
+            let king_to = self.castle_columns(wing).0;
+            if crosses(from.1, king_to).any(|column| self.attacked((from.0, column), enemy)) {
+                continue;
+            }
+            if self.chess960 {
+                moves.push(Move::new(from, rook));
+            } else {
+                moves.push(Move::new(from, (from.0, king_to)));
+            }
+        }
+    }
+
+    //every piece in the pocket onto every empty square, pawns not on the first
+    //or last rank
+    fn drop_moves(&self, moves: &mut Vec<Move>) {
+        let pocket = self.pockets[side_index(self.turn)];
+        for (index, piece) in POCKET.iter().enumerate() {
+            if pocket[index] == 0 {
+                continue;
+            }
+            for to in self.squares() {
+                let edge = to.0 == 0 || to.0 == self.rows() - 1;
+                if self.is_empty(to) && !(piece.rules().pawn_like() && edge) {
+                    moves.push(Move::drop(*piece, to));
+                }
+            }
+        }
+    }
+
+    //nothing but the king and rook themselves on the squares either of them
+    //crosses, which also works when they start somewhere other than e1 and h1
+    fn castling_path_clear(&self, king: (usize, usize), rook_column: usize, wing: usize) -> bool {
+        let (king_to, rook_to) = self.castle_columns(wing);
+        !crosses(king.1, king_to)
+            .chain(crosses(rook_column, rook_to))
+            .any(|column| {
+                column != king.1 && column != rook_column && !self.is_empty((king.0, column))
+            })
+    }
+
+    //plays a move that is assumed to be legal, keeping castling rights, the en
+    //passant square, the move counters and the key up to date. what it returns
+    //takes the move back with unmake_move
+    pub fn make_move(&mut self, chosen: Move) -> Undo {
+        let mut undo = Undo {
+            squares: Vec::new(),
+            promoted: Vec::new(),
+            castling: self.castling,
+            en_passant: self.en_passant,
+            halfmove: self.halfmove,
+            fullmove: self.fullmove,
+            checks: self.checks,
+            pockets: self.pockets,
+            key: self.key,
+        };
+        if let Some(piece) = chosen.drop {
+            self.drop_from_pocket(piece, chosen.to, &mut undo);
+            return undo;
+        }
+        let (piece, colour) = self.piece_at(chosen.from);
+        let rules = piece.rules();
+        let side = side_index(colour);
+        let castle = self.castling_wing(chosen.from, chosen.to);
+        let capture = castle.is_none() && !self.is_empty(chosen.to);
+
+        let en_passant = rules.pawn_like() && chosen.from.1 != chosen.to.1 && !capture;
+        if self.variant.rules == Rules::Crazyhouse {
+            //promoted pieces go back to being pawns when they are taken
+            let (taken, _) = self.piece_at(chosen.to);
+            let taken = if en_passant || self.promoted[chosen.to.0][chosen.to.1] {
+                Piece::Pawn
+            } else {
+                taken
+            };
+            if let Some(index) = pocket_index(taken).filter(|_| capture || en_passant) {
+                //a pocket can't hold more than u8::MAX, so the count stops there
+                self.set_pocket(side, index, self.pockets[side][index].saturating_add(1));
+            }
+            for square in [chosen.to, chosen.from] {
+                undo.promoted
+                    .push((square, self.promoted[square.0][square.1]));
+            }
+            self.promoted[chosen.to.0][chosen.to.1] =
+                self.promoted[chosen.from.0][chosen.from.1] || chosen.promotion.is_some();
+            self.promoted[chosen.from.0][chosen.from.1] = false;
+        }
+        self.key ^= self.castling_key();
+        if en_passant {
+            //en passant, the pawn taken is beside the one taking it
+            let taken = (chosen.from.0, chosen.to.1);
+            self.place(taken, vacant(taken), &mut undo);
+        }
+        let rook_column = castle.and_then(|wing| self.castling[side][wing]);
+        if let (Some(wing), Some(rook_column)) = (castle, rook_column) {
+            //both are lifted off first, in chess960 either may land where the other stood
+            let row = chosen.from.0;
+            let (king_to, rook_to) = self.castle_columns(wing);
+            let king = self.board[row][chosen.from.1];
+            let rook = self.board[row][rook_column];
+            self.place(chosen.from, vacant(chosen.from), &mut undo);
+            self.place((row, rook_column), vacant((row, rook_column)), &mut undo);
+            self.place((row, king_to), king, &mut undo);
+            self.place((row, rook_to), rook, &mut undo);
+        } else {
+            self.switch_pieces(chosen.from, chosen.to, &mut undo);
+            if let Some(promotion) = chosen.promotion {
+                let block = BlockData {
+                    colour,
+                    piece: promotion,
+                };
+                self.place(chosen.to, block, &mut undo);
+            }
+        }
+
+        if self.variant.rules == Rules::Atomic && (capture || en_passant) {
+            self.explode(chosen.to, &mut undo);
+        }
 
-                self.switch_pieces(piece_coords, self.arrow);
-                self.toggle_turn();
-                return true;
+        //moving the king or a rook, or having the rook taken, loses the right to castle
+        if rules.royal() {
+            self.castling[side] = [None, None];
+        }
+        let home_rows = [0, self.rows() - 1];
+        for (rights, home_row) in self.castling.iter_mut().zip(home_rows) {
+            for right in rights.iter_mut() {
+                if let Some(column) = *right {
+                    if chosen.from == (home_row, column) || chosen.to == (home_row, column) {
+                        *right = None;
+                    }
+                }
             }
+        }
+        self.key ^= self.castling_key();
+
+        self.en_passant = None;
+        if rules.pawn_like() && chosen.from.0.abs_diff(chosen.to.0) == 2 {
+            self.en_passant = Some(((chosen.from.0 + chosen.to.0) / 2, chosen.from.1));
+        }
+        if rules.pawn_like() || capture {
+            self.halfmove = 0;
+        } else {
+            self.halfmove += 1;
+        }
+        if colour == Colour::Black {
+            self.fullmove += 1;
+        }
+        self.toggle_turn();
+        if self.variant.rules == Rules::ThreeCheck && self.in_check(self.turn) {
+            self.key ^= zobrist::checks(side, self.checks[side] as usize);
+            self.checks[side] += 1;
+            self.key ^= zobrist::checks(side, self.checks[side] as usize);
+        }
+        undo
+    }
+
+    //puts the board back the way it was before the move `undo` came from
+    pub fn unmake_move(&mut self, undo: Undo) {
+        for (coords, block) in undo.squares.into_iter().rev() {
+            self.board[coords.0][coords.1] = block;
+        }
+        for (coords, promoted) in undo.promoted.into_iter().rev() {
+            self.promoted[coords.0][coords.1] = promoted;
+        }
+        self.turn = self.turn.opponent();
+        self.castling = undo.castling;
+        self.en_passant = undo.en_passant;
+        self.halfmove = undo.halfmove;
+        self.fullmove = undo.fullmove;
+        self.checks = undo.checks;
+        self.pockets = undo.pockets;
+        self.key = undo.key;
+    }
+
+    //a crazyhouse drop, which counts as a move like any other but can't capture,
+    //castle or be taken en passant
+    fn drop_from_pocket(&mut self, piece: Piece, to: (usize, usize), undo: &mut Undo) {
+        let side = side_index(self.turn);
+        if let Some(index) = pocket_index(piece) {
+            self.set_pocket(side, index, self.pockets[side][index].saturating_sub(1));
+        }
+        let block = BlockData {
+            colour: self.turn,
+            piece,
+        };
+        self.place(to, block, undo);
+        self.en_passant = None;
+        self.halfmove += 1;
+        if self.turn == Colour::Black {
+            self.fullmove += 1;
+        }
+        self.toggle_turn();
+    }
 
-            Piece::Bishop => {
-                if self.arrow == piece_coords {
-                    return false;
+    //an atomic capture: the piece that took goes with everything around it
+    //except pawns, and rooks blown off their corner can't castle any more
+    fn explode(&mut self, centre: (usize, usize), undo: &mut Undo) {
+        self.place(centre, vacant(centre), undo);
+        for displace in pieces::KING_OFFSETS {
+            if let Some(square) = self.offset(centre, displace) {
+                if self.piece_at(square).0 != Piece::Pawn {
+                    self.place(square, vacant(square), undo);
                 }
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
+            }
+        }
+        for (side, colour) in [Colour::White, Colour::Black].into_iter().enumerate() {
+            let row = self.home_row(colour);
+            for wing in 0..2 {
+                if let Some(column) = self.castling[side][wing] {
+                    if self.piece_at((row, column)) != (Piece::Rook, colour) {
+                        self.castling[side][wing] = None;
+                    }
                 }
-                if self.arrow.0 + self.arrow.1 != piece_coords.0 + piece_coords.1
-                    && self.arrow.0 as i32 - self.arrow.1 as i32
-                        != piece_coords.0 as i32 - piece_coords.1 as i32
-                {
-                    return false;
+            }
+        }
+    }
+
+    //who has won by the variant's own rules, before looking at mate: a king
+    //blown up, a king on the hill or a third check. None while the game goes on
+    pub fn variant_win(&self) -> Option<Colour> {
+        for colour in [Colour::White, Colour::Black] {
+            let won = match self.variant.rules {
+                Rules::Standard | Rules::Crazyhouse => false,
+                Rules::Atomic | Rules::FogOfWar => self.king(colour.opponent()).is_none(),
+                Rules::Horde => {
+                    colour == Colour::Black
+                        && !self
+                            .squares()
+                            .any(|square| self.is_friend(square, Colour::White))
                 }
-                //disable bishop piece hopping
-                if !self.check_piece_hop(piece_coords, self.arrow) {
-                    return false;
+                Rules::KingOfTheHill => self.king(colour).is_some_and(|king| {
+                    let middle =
+                        |line: usize, size: usize| (size - 1) / 2 <= line && line <= size / 2;
+                    middle(king.0, self.rows()) && middle(king.1, self.columns())
+                }),
+                Rules::ThreeCheck => self.checks[side_index(colour)] >= 3,
+                //stalemated counts as well, having no pieces is just one way to be
+                Rules::Antichess => colour == self.turn && self.legal_moves().is_empty(),
+            };
+            if won {
+                return Some(colour);
+            }
+        }
+        None
+    }
+
+    //what `colour` can see in fog of war: the squares its pieces stand on and
+    //every square one of them could move to
+    pub fn visible(&self, colour: Colour) -> [[bool; MAX_SIZE]; MAX_SIZE] {
+        let mut seen = [[false; MAX_SIZE]; MAX_SIZE];
+        let mut own = self.clone();
+        if own.turn != colour {
+            own.turn = colour;
+            //only the side to move could take en passant
+            own.en_passant = None;
+        }
+        for square in self.squares() {
+            if self.piece_at(square).1 == colour {
+                seen[square.0][square.1] = true;
+            }
+        }
+        for candidate in own.pseudo_legal_moves() {
+            seen[candidate.to.0][candidate.to.1] = true;
+        }
+        seen
+    }
+
+    pub fn in_check(&self, colour: Colour) -> bool {
+        if !self.variant.rules.royal_king() {
+            return false;
+        }
+        match (self.king(colour), self.king(colour.opponent())) {
+            //taking a king standing next to the other one would blow up both
+            (Some(king), Some(other))
+                if self.variant.rules == Rules::Atomic
+                    && king.0.abs_diff(other.0) <= 1
+                    && king.1.abs_diff(other.1) <= 1 =>
+            {
+                false
+            }
+            (Some(king), _) => self.attacked(king, colour.opponent()),
+            (None, _) => false,
+        }
+    }
+
+    fn king(&self, colour: Colour) -> Option<(usize, usize)> {
+        for square in self.squares() {
+            let (piece, owner) = self.piece_at(square);
+            if owner == colour && piece.rules().royal() {
+                return Some(square);
+            }
+        }
+        None
+    }
+
+    //true if any piece of `by` could take on the square
+    pub fn attacked(&self, square: (usize, usize), by: Colour) -> bool {
+        //looks from the square back along each way a piece could reach it
+        for piece in pieces::ALL {
+            let rules = piece.rules();
+            if rules.royal() && self.variant.rules == Rules::Atomic {
+                //kings can't take anything in atomic
+                continue;
+            }
+            let holds = |coords: (usize, usize)| self.piece_at(coords) == (piece, by);
+            for displace in rules.leaps().iter().chain(rules.takes()) {
+                let (rows, columns) = facing(*displace, by);
+                if self.offset(square, (-rows, -columns)).is_some_and(holds) {
+                    return true;
                 }
-                self.switch_pieces(piece_coords, self.arrow);
-                self.toggle_turn();
-                return true;
             }
-            Piece::Queen => {
-                if self.arrow == piece_coords {
-                    return false;
+            for direction in rules.slides() {
+                let (rows, columns) = facing(*direction, by);
+                let mut current = square;
+                while let Some(next) = self.offset(current, (-rows, -columns)) {
+                    if !self.is_empty(next) {
+                        if holds(next) {
+                            return true;
+                        }
+                        break;
+                    }
+                    current = next;
                 }
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
+            }
+        }
+        false
+    }
+
+    fn is_empty(&self, coords: (usize, usize)) -> bool {
+        self.board[coords.0][coords.1].piece == Piece::Null
+    }
+
+    fn is_friend(&self, coords: (usize, usize), colour: Colour) -> bool {
+        let block = self.board[coords.0][coords.1];
+        block.piece != Piece::Null && block.colour == colour
+    }
+
+    //the position as a FEN string
+    pub fn to_fen(&self) -> String {
+        let turn = if self.turn == Colour::Black { "b" } else { "w" };
+        let mut castling = String::new();
+        for (side, rights) in self.castling.iter().enumerate() {
+            for (wing, right) in rights.iter().enumerate() {
+                if let Some(column) = *right {
+                    //x-fen, K and Q unless another rook is further out on that side
+                    let colour = [Colour::White, Colour::Black][side];
+                    let further = if wing == 0 {
+                        column + 1..self.columns()
+                    } else {
+                        0..column
+                    };
+                    let letter = if self.chess960
+                        && further.into_iter().any(|other| {
+                            self.piece_at((self.home_row(colour), other)) == (Piece::Rook, colour)
+                        }) {
+                        (b'a' + column as u8) as char
+                    } else {
+                        ['k', 'q'][wing]
+                    };
+                    castling.push(if side == 0 {
+                        letter.to_ascii_uppercase()
+                    } else {
+                        letter
+                    });
                 }
-                if !self.check_piece_hop(piece_coords, self.arrow) {
-                    return false;
+            }
+        }
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        let en_passant = match self.en_passant {
+            Some(square) => square_name(square),
+            None => String::from("-"),
+        };
+        let mut placement = self.placement();
+        //crazyhouse marks promoted pieces with a ~ and adds the pockets, like [Qnp]
+        if self.variant.rules == Rules::Crazyhouse {
+            placement.push('[');
+            for (side, pocket) in self.pockets.iter().enumerate() {
+                for (index, count) in pocket.iter().enumerate() {
+                    let letter = POCKET[index].letter();
+                    let letter = if side == 0 {
+                        letter.to_ascii_uppercase()
+                    } else {
+                        letter
+                    };
+                    placement.extend(std::iter::repeat_n(letter, *count as usize));
                 }
+            }
+            placement.push(']');
+        }
+        let mut fen = format!(
+            "{} {} {} {} {} {}",
+            placement, turn, castling, en_passant, self.halfmove, self.fullmove
+        );
+        //three-check adds the checks given so far, like +1+0
+        if self.variant.rules == Rules::ThreeCheck {
+            fen.push_str(&format!(" +{}+{}", self.checks[0], self.checks[1]));
+        }
+        fen
+    }
+
+    //reads a FEN string, the move counters can be left off
+    pub fn from_fen(fen: &str) -> Result<Matrix, String> {
+        Matrix::from_fen_in(fen, None)
+    }
 
-                self.switch_pieces(piece_coords, self.arrow);
-                self.toggle_turn();
-                return true;
+    //reads a FEN for a variant, which has to be played on a board of that size.
+    //without one the variant is worked out from the size
+    pub fn from_fen_in(fen: &str, variant: Option<&'static Variant>) -> Result<Matrix, String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 4 {
+            return Err(format!(
+                "a FEN needs at least 4 fields, found {}",
+                fields.len()
+            ));
+        }
+        let (placement, pockets) = match fields[0].split_once('[') {
+            Some((placement, pockets)) => (
+                placement,
+                Some(
+                    pockets
+                        .strip_suffix(']')
+                        .ok_or("the pockets need a closing ]")?,
+                ),
+            ),
+            None => (fields[0], None),
+        };
+        let (rows, columns) = placement_size(placement);
+        let variant = match variant {
+            Some(variant) if (variant.rows, variant.columns) == (rows, columns) => variant,
+            Some(variant) => {
+                return Err(format!("the position is not on a {} board", variant.title))
             }
-            Piece::King => {
-                if self.arrow == piece_coords {
-                    return false;
+            None => variants::by_size(rows, columns)
+                .ok_or_else(|| format!("no variant is played on a {}x{} board", columns, rows))?,
+        };
+        let mut matrix = Matrix::empty(variant);
+        matrix.set_placement(placement)?;
+        for letter in pockets.unwrap_or("").chars() {
+            let index = Piece::from_letter(letter)
+                .and_then(pocket_index)
+                .ok_or_else(|| format!("`{}` can't be in a pocket", letter))?;
+            let side = letter.is_ascii_lowercase() as usize;
+            matrix.pockets[side][index] = matrix.pockets[side][index]
+                .checked_add(1)
+                .ok_or_else(|| format!("too many `{}` in the pockets", letter))?;
+        }
+        matrix.turn = match fields[1] {
+            "w" => Colour::White,
+            "b" => Colour::Black,
+            other => return Err(format!("side to move must be w or b, not `{}`", other)),
+        };
+        matrix.castling = [[None, None], [None, None]];
+        if fields[2] != "-" {
+            for letter in fields[2].chars() {
+                let side = if letter.is_ascii_uppercase() { 0 } else { 1 };
+                let colour = [Colour::White, Colour::Black][side];
+                let row = matrix.home_row(colour);
+                let last = columns - 1;
+                let is_rook =
+                    |column: &usize| matrix.piece_at((row, *column)) == (Piece::Rook, colour);
+                let start = variant
+                    .back_rank
+                    .iter()
+                    .position(|piece| *piece == Piece::King)
+                    .unwrap_or(columns / 2);
+                let king = (0..columns)
+                    .find(|column| matrix.piece_at((row, *column)) == (Piece::King, colour))
+                    .unwrap_or(start);
+                //K and Q are the outermost rook on that side (x-fen), a file
+                //letter names the rook (shredder-fen)
+                let (wing, column) = match letter.to_ascii_lowercase() {
+                    'k' => (0, (king + 1..columns).rev().find(is_rook).unwrap_or(last)),
+                    'q' => (1, (0..king).find(is_rook).unwrap_or(0)),
+                    file if ('a'..).take(columns).any(|other| other == file) => {
+                        let column = file as usize - 'a' as usize;
+                        ((column < king) as usize, column)
+                    }
+                    _ => return Err(format!("unknown castling right `{}`", letter)),
+                };
+                matrix.castling[side][wing] = Some(column);
+                if king != start || column != [last, 0][wing] {
+                    matrix.chess960 = true;
                 }
-                if !self.validate_friend(piece_type.colour, self.arrow) {
-                    return false;
+            }
+        }
+        matrix.en_passant = match fields[3] {
+            "-" => None,
+            square => Some(
+                parse_square(square)
+                    .filter(|coords| coords.0 < rows && coords.1 < columns)
+                    .ok_or_else(|| format!("bad en passant square `{}`", square))?,
+            ),
+        };
+        let counter = |index: usize, default: u32| match fields.get(index) {
+            Some(text) => text
+                .parse()
+                .map_err(|_| format!("bad move counter `{}`", text)),
+            None => Ok(default),
+        };
+        matrix.halfmove = counter(4, 0)?;
+        matrix.fullmove = counter(5, 1)?;
+        if let Some(checks) = fields.get(6) {
+            let given: Vec<Option<u8>> = checks
+                .strip_prefix('+')
+                .unwrap_or("")
+                .split('+')
+                .map(|count| count.parse().ok().filter(|count| *count <= 3))
+                .collect();
+            match given[..] {
+                [Some(white), Some(black)] => matrix.checks = [white, black],
+                _ => return Err(format!("bad check count `{}`, expected like +1+0", checks)),
+            }
+        }
+        //the horde has no king
+        let kings_missing = (matrix.king(Colour::White).is_none() && variant.rules != Rules::Horde)
+            || matrix.king(Colour::Black).is_none();
+        if kings_missing && variant.rules.royal_king() {
+            return Err(String::from("both sides need a king"));
+        }
+        matrix.key = matrix.fresh_key();
+        Ok(matrix)
+    }
+
+    //the pieces written rank by rank from the eighth down, like the first field of
+    //a FEN: uppercase is white, lowercase black and digits count empty squares
+    pub fn placement(&self) -> String {
+        let mut ranks = Vec::new();
+        for row in (0..self.rows()).rev() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for (column, block) in self.board[row][..self.columns()].iter().enumerate() {
+                if block.piece == Piece::Null {
+                    empty += 1;
+                    continue;
                 }
-                if !(self.arrow.0==piece_coords.0+1 || self.arrow.0 as i32 ==piece_coords.0 as i32-1 || self.arrow.0 == piece_coords.0){
-                    return false;
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                    empty = 0;
                 }
-                if !(self.arrow.1==piece_coords.1+1 || self.arrow.1 as i32 ==piece_coords.1 as i32 -1 || self.arrow.1 == piece_coords.1){
-                    return false;
+                let letter = block.piece.letter();
+                rank.push(if block.colour == Colour::White {
+                    letter.to_ascii_uppercase()
+                } else {
+                    letter
+                });
+                if self.variant.rules == Rules::Crazyhouse && self.promoted[row][column] {
+                    rank.push('~');
                 }
-
-                self.switch_pieces(piece_coords, self.arrow);
-                self.toggle_turn();
-                return true;
             }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+        ranks.join("/")
+    }
 
-            Piece::Null => {
-                return false;
+    //the board is only changed if the whole placement is valid
+    fn set_placement(&mut self, text: &str) -> Result<(), String> {
+        let (rows, columns) = (self.rows(), self.columns());
+        let ranks: Vec<&str> = text.split('/').collect();
+        if ranks.len() != rows {
+            return Err(format!("expected {} ranks, found {}", rows, ranks.len()));
+        }
+        let mut board = Matrix::empty(self.variant).board;
+        let mut promoted = [[false; MAX_SIZE]; MAX_SIZE];
+        for (index, rank) in ranks.iter().enumerate() {
+            let row = rows - 1 - index;
+            let mut column = 0;
+            for (empty, letter) in rank_squares(rank) {
+                column += empty;
+                if column > columns {
+                    return Err(format!(
+                        "rank {} has more than {} squares",
+                        row + 1,
+                        columns
+                    ));
+                }
+                let letter = match letter {
+                    //a promoted piece in crazyhouse, the square just before
+                    Some('~') if column > 0 => {
+                        promoted[row][column - 1] = true;
+                        continue;
+                    }
+                    Some(letter) => letter,
+                    None => continue,
+                };
+                let piece = Piece::from_letter(letter)
+                    .ok_or_else(|| format!("unknown piece `{}`", letter))?;
+                if column >= columns {
+                    return Err(format!(
+                        "rank {} has more than {} squares",
+                        row + 1,
+                        columns
+                    ));
+                }
+                board[row][column] = BlockData {
+                    colour: if letter.is_ascii_uppercase() {
+                        Colour::White
+                    } else {
+                        Colour::Black
+                    },
+                    piece,
+                };
+                column += 1;
+            }
+            if column != columns {
+                return Err(format!(
+                    "rank {} does not have {} squares",
+                    row + 1,
+                    columns
+                ));
             }
         }
+        self.board = board;
+        self.promoted = promoted;
+        Ok(())
     }
 
     pub fn select_piece(&mut self) {
         self.selected_piece = (self.arrow.0, self.arrow.1);
     }
 
+    //the variant's start position, the same back row on both sides
     pub fn populate_pieces(&mut self) {
+        if self.variant.rules == Rules::Horde {
+            return self.populate_horde();
+        }
+        for colour in [Colour::White, Colour::Black] {
+            self.pawn_layout(self.pawn_row(colour), colour);
+            self.piece_layout(self.home_row(colour), colour, self.variant.back_rank);
+        }
+    }
+
+    //black as usual against four rows of white pawns, with four more on the
+    //b, c, f and g files in front of them. white has nothing to castle with
+    fn populate_horde(&mut self) {
+        for row in 0..4 {
+            self.pawn_layout(row, Colour::White);
+        }
+        for column in [1, 2, 5, 6] {
+            let pawn = BlockData {
+                colour: Colour::White,
+                piece: Piece::Pawn,
+            };
+            self.put((4, column), pawn);
+        }
+        self.pawn_layout(self.pawn_row(Colour::Black), Colour::Black);
+        let home = self.home_row(Colour::Black);
+        self.piece_layout(home, Colour::Black, self.variant.back_rank);
+        self.castling[0] = [None, None];
+        self.key = self.fresh_key();
+    }
+
+    //takes the pieces off the squares, to give odds
+    pub fn remove_pieces(&mut self, squares: &[(usize, usize)]) {
+        for square in squares {
+            self.clear(*square);
+        }
+    }
+
+    //chess960 start position `number`, both sides may castle with the rooks
+    //wherever they ended up
+    pub fn populate_chess960(&mut self, number: u32) {
+        let layout = chess960_rank(number);
         self.pawn_layout(1, Colour::White);
         self.pawn_layout(6, Colour::Black);
-        self.piece_layout(0, Colour::White);
-        self.piece_layout(7, Colour::Black);
+        self.piece_layout(0, Colour::White, &layout);
+        self.piece_layout(7, Colour::Black, &layout);
+        let mut rooks = (0..8).filter(|column| layout[*column] == Piece::Rook);
+        let queen_side = rooks.next();
+        let king_side = rooks.next();
+        self.castling = [[king_side, queen_side]; 2];
+        self.chess960 = true;
+        self.key = self.fresh_key();
     }
 
+    //displace is in screen directions, so up stays up whichever side is on top
     pub fn mutate_arrow(&mut self, displace: (i32, i32)) {
-        if self.arrow.0 == 7 && displace.0 > 0 {
+        let displace = if self.flipped {
+            (-displace.0, displace.1)
+        } else {
+            (displace.0, -displace.1)
+        };
+        let (last_row, last_column) = (self.rows() - 1, self.columns() - 1);
+        if self.arrow.0 == last_row && displace.0 > 0 {
             self.arrow.0 = 0;
         } else if self.arrow.0 == 0 && displace.0 < 0 {
-            self.arrow.0 = 7;
-        } else if self.arrow.1 == 7 && displace.1 > 0 {
+            self.arrow.0 = last_row;
+        } else if self.arrow.1 == last_column && displace.1 > 0 {
             self.arrow.1 = 0;
         } else if self.arrow.1 == 0 && displace.1 < 0 {
-            self.arrow.1 = 7;
+            self.arrow.1 = last_column;
         } else {
             self.arrow.0 = (self.arrow.0 as i32 + displace.0) as usize;
             self.arrow.1 = (self.arrow.1 as i32 + displace.1) as usize;
         }
     }
 
-    pub fn display(&mut self) {
-        for (index, row) in self.board.iter().enumerate() {
+    //draws the board into its area of the frame, with the row marker in the
+    //first column, the file letters under the board and the column marker under
+    //them. with a viewer only what that side can see is drawn, the rest is fog
+    pub fn display(
+        &self,
+        theme: &Theme,
+        frame: &mut Frame,
+        area: &BoardArea,
+        viewer: Option<Colour>,
+    ) {
+        let (width, height) = (area.square_width(), area.square_height());
+        let seen = viewer.map(|colour| self.visible(colour));
+        for screen_row in 0..self.rows() {
+            let index = self.orient_row(screen_row);
+            let y = area.y + screen_row as u16 * height;
             if index == self.arrow.0 {
-                print!(">");
-            } else {
-                print!(" ");
+                frame.print(area.x, y + height / 2, ">", Style::default());
             }
-            for item in row {
-                let ref_colour = item.colour;
-                print!("{}", item.piece.to_string(ref_colour));
+            for screen_column in 0..self.columns() {
+                let coords = (index, self.orient_column(screen_column));
+                let item = self.board[coords.0][coords.1];
+                let hidden = seen.is_some_and(|seen| !seen[coords.0][coords.1]);
+                let (text, style) = if hidden {
+                    theme.fog(coords == self.arrow)
+                } else {
+                    theme.cell(
+                        item.piece,
+                        item.colour,
+                        square_shade(coords),
+                        coords == self.arrow,
+                    )
+                };
+                let x = area.x + 1 + screen_column as u16 * width;
+                //bigger squares are filled in first and the glyph sits in the middle
+                let (fill, fill_style) = if hidden {
+                    theme.fog(coords == self.arrow)
+                } else {
+                    theme.fill(square_shade(coords), coords == self.arrow)
+                };
+                for line in 0..height {
+                    for part in 0..area.scale {
+                        frame.print_wide(
+                            x + part * CELL_WIDTH,
+                            y + line,
+                            fill,
+                            CELL_WIDTH,
+                            fill_style,
+                        );
+                    }
+                }
+                let middle = (width - CELL_WIDTH) / 2;
+                frame.print_wide(x + middle, y + height / 2, text, CELL_WIDTH, style);
             }
-            print!("\n");
         }
-        print!(" {}^", " ".repeat(self.arrow.1 * 2));
+        let bottom = area.y + self.rows() as u16 * height;
+        let centre =
+            |screen_column: usize| area.x + 1 + screen_column as u16 * width + (width - 1) / 2;
+        for screen_column in 0..self.columns() {
+            let file = (b'a' + self.orient_column(screen_column) as u8) as char;
+            frame.print(
+                centre(screen_column),
+                bottom,
+                &file.to_string(),
+                Style::default(),
+            );
+        }
+        let arrow_column = self.orient_column(self.arrow.1);
+        frame.print(centre(arrow_column), bottom + 1, "^", Style::default());
+    }
+
+    //turns a terminal cell back into board coordinates, None if it isn't on the board
+    pub fn square_at(&self, column: u16, row: u16, area: &BoardArea) -> Option<(usize, usize)> {
+        if column < area.x + 1 || row < area.y {
+            return None;
+        }
+        let screen_column = ((column - area.x - 1) / area.square_width()) as usize;
+        let screen_row = ((row - area.y) / area.square_height()) as usize;
+        if screen_column >= self.columns() || screen_row >= self.rows() {
+            return None;
+        }
+        Some((
+            self.orient_row(screen_row),
+            self.orient_column(screen_column),
+        ))
+    }
+
+    //maps a screen row to the board one and back, it is the same both ways.
+    //the board is seen from the side at the bottom, with its a file on the left:
+    //white at the top puts rank 1 and the a file at the top right, black at the
+    //top puts rank 1 at the bottom and the a file on the left
+    fn orient_row(&self, line: usize) -> usize {
+        if self.flipped {
+            self.rows() - 1 - line
+        } else {
+            line
+        }
+    }
 
-        print!("\n");
+    fn orient_column(&self, line: usize) -> usize {
+        if self.flipped {
+            line
+        } else {
+            self.columns() - 1 - line
+        }
     }
 
     fn pawn_layout(&mut self, row: usize, colour: Colour) {
-        for item in 0..8 {
-            let ref_board = &mut self.board[row][item];
-            ref_board.piece = Piece::Pawn;
-            ref_board.colour = if colour == Colour::White {
-                Colour::White
-            } else {
-                Colour::Black
+        for item in 0..self.columns() {
+            let pawn = BlockData {
+                colour,
+                piece: Piece::Pawn,
             };
+            self.put((row, item), pawn);
         }
     }
-    fn piece_layout(&mut self, row: usize, colour: Colour) {
-        let layout = [
-            Piece::Rook,
-            Piece::Knight,
-            Piece::Bishop,
-            if colour == Colour::White {
-                Piece::King
-            } else {
-                Piece::Queen
-            },
-            if colour == Colour::White {
-                Piece::Queen
-            } else {
-                Piece::King
-            },
-            Piece::Bishop,
-            Piece::Knight,
-            Piece::Rook,
-        ];
+    fn piece_layout(&mut self, row: usize, colour: Colour, layout: &[Piece]) {
         for (index, item) in layout.iter().enumerate() {
-            let ref_board = &mut self.board[row][index];
-            ref_board.colour = colour;
-            ref_board.piece = *item;
+            let piece = BlockData {
+                colour,
+                piece: *item,
+            };
+            self.put((row, index), piece);
+        }
+    }
+}
+
+//the back row of chess960 start position `number` out of 960, numbered the way
+//Scharnagl did: the bishops on opposite colours, then the queen and the knights
+//on what is still free, and the king between the rooks in the last three squares
+pub fn chess960_rank(number: u32) -> [Piece; 8] {
+    //the knight pairs, as which of the five free squares they take
+    const KNIGHTS: [(usize, usize); 10] = [
+        (0, 1),
+        (0, 2),
+        (0, 3),
+        (0, 4),
+        (1, 2),
+        (1, 3),
+        (1, 4),
+        (2, 3),
+        (2, 4),
+        (3, 4),
+    ];
+    let free = |rank: &[Piece; 8], nth: usize| {
+        (0..8)
+            .filter(|column| rank[*column] == Piece::Null)
+            .nth(nth)
+            .unwrap_or(0)
+    };
+    let mut rank = [Piece::Null; 8];
+    let mut rest = number as usize % 960;
+    rank[rest % 4 * 2 + 1] = Piece::Bishop;
+    rest /= 4;
+    rank[rest % 4 * 2] = Piece::Bishop;
+    rest /= 4;
+    rank[free(&rank, rest % 6)] = Piece::Queen;
+    rest /= 6;
+    let (first, second) = KNIGHTS[rest];
+    let knights = [free(&rank, first), free(&rank, second)];
+    for column in knights {
+        rank[column] = Piece::Knight;
+    }
+    for piece in [Piece::Rook, Piece::King, Piece::Rook] {
+        let column = free(&rank, 0);
+        rank[column] = piece;
+    }
+    rank
+}
+
+//every even row starts with a black tile and every odd row with a white one
+fn square_shade(coords: (usize, usize)) -> Colour {
+    if (coords.0 + coords.1).is_multiple_of(2) {
+        Colour::Black
+    } else {
+        Colour::White
+    }
+}
+
+//algebraic name of a square, row 0 is the first rank and column 0 the a file
+//empty squares keep the shade of the square as their colour
+fn vacant(coords: (usize, usize)) -> BlockData {
+    BlockData {
+        colour: square_shade(coords),
+        piece: Piece::Null,
+    }
+}
+
+pub fn square_name(coords: (usize, usize)) -> String {
+    format!("{}{}", (b'a' + coords.1 as u8) as char, coords.0 + 1)
+}
+
+//reads a square name like e4 or a10 back into board coordinates, anywhere on
+//the biggest board a variant can have
+pub fn parse_square(name: &str) -> Option<(usize, usize)> {
+    match split_square(name)? {
+        (coords, "") => Some(coords),
+        _ => None,
+    }
+}
+
+//the square at the start of `text` and whatever follows it
+fn split_square(text: &str) -> Option<((usize, usize), &str)> {
+    //a letter, so the rank starts one byte in
+    let file = text.chars().next().filter(char::is_ascii_lowercase)?;
+    let digits = text[1..]
+        .find(|letter: char| !letter.is_ascii_digit())
+        .map_or(text.len(), |end| end + 1);
+    let rank: usize = text.get(1..digits)?.parse().ok()?;
+    let column = (file as usize).checked_sub('a' as usize)?;
+    if column >= MAX_SIZE || !(1..=MAX_SIZE).contains(&rank) {
+        return None;
+    }
+    Some(((rank - 1, column), &text[digits..]))
+}
+
+//how many ranks a FEN placement has and how many squares the first of them
+fn placement_size(text: &str) -> (usize, usize) {
+    let first = text.split('/').next().unwrap_or("");
+    let columns = rank_squares(first)
+        .map(|(empty, letter)| empty + letter.is_some_and(|letter| letter != '~') as usize)
+        .sum();
+    (text.split('/').count(), columns)
+}
+
+//a rank of a FEN placement as (empty squares, piece letter) pairs, so a count of
+//ten or more empty squares reads as one number. counts stop one past the biggest
+//board, anything longer is too long either way
+fn rank_squares(rank: &str) -> impl Iterator<Item = (usize, Option<char>)> + '_ {
+    let mut letters = rank.chars().peekable();
+    std::iter::from_fn(move || {
+        let mut empty = 0;
+        while let Some(digit) = letters.peek().and_then(|letter| letter.to_digit(10)) {
+            empty = (empty * 10 + digit as usize).min(MAX_SIZE + 1);
+            letters.next();
+        }
+        match letters.next() {
+            Some(letter) => Some((empty, Some(letter))),
+            None if empty > 0 => Some((empty, None)),
+            None => None,
         }
+    })
+}
+
+//where a piece goes in the pockets, None for kings and pieces that can't be in one
+fn pocket_index(piece: Piece) -> Option<usize> {
+    POCKET.iter().position(|other| *other == piece)
+}
+
+//every column from start to end, both included, whichever way round they are
+fn crosses(start: usize, end: usize) -> std::ops::RangeInclusive<usize> {
+    start.min(end)..=start.max(end)
+}
+
+//which way a colour's pawns move up the rows
+fn forward(colour: Colour) -> i32 {
+    if colour == Colour::Black {
+        -1
+    } else {
+        1
+    }
+}
+
+//an offset from the rules, which are written for white, as `colour` sees it
+fn facing(displace: (i32, i32), colour: Colour) -> (i32, i32) {
+    (displace.0 * forward(colour), displace.1)
+}
+
+fn side_index(colour: Colour) -> usize {
+    (colour == Colour::Black) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moves_from(fen: &str, square: &str) -> Vec<String> {
+        let board = Matrix::from_fen(fen).unwrap();
+        let from = parse_square(square).unwrap();
+        let mut names: Vec<String> = board
+            .legal_moves()
+            .iter()
+            .filter(|candidate| candidate.from == from)
+            .map(|candidate| candidate.name())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn knight_in_the_corner_stays_on_the_board() {
+        assert_eq!(
+            moves_from("4k3/8/8/8/8/8/8/N3K3 w - - 0 1", "a1"),
+            ["a1b3", "a1c2"]
+        );
+    }
+
+    #[test]
+    fn bishop_does_not_wrap_around_the_edge() {
+        assert_eq!(
+            moves_from("4k3/8/8/8/8/8/6P1/4K2B w - - 0 1", "h1"),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn no_castling_through_check() {
+        let moves = moves_from("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1", "e1");
+        assert!(!moves.contains(&String::from("e1g1")));
+        assert!(moves.contains(&String::from("e1c1")));
+    }
+
+    #[test]
+    fn en_passant_removes_the_pawn() {
+        let mut board = Matrix::from_fen("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        board.make_move(Move::parse("e5d6").unwrap());
+        assert_eq!(board.placement(), "4k3/8/3P4/8/8/8/8/4K3");
+    }
+
+    #[test]
+    fn pocket_counts_stop_at_u8_max() {
+        let fen = |count: usize| format!("4k3/8/8/8/8/8/8/4K3[{}] w - - 0 1", "P".repeat(count));
+        let pawns = pocket_index(Piece::Pawn).unwrap();
+        assert_eq!(Matrix::from_fen(&fen(255)).unwrap().pockets[0][pawns], 255);
+        assert_eq!(
+            Matrix::from_fen(&fen(256)).err().unwrap(),
+            "too many `P` in the pockets"
+        );
+    }
+
+    #[test]
+    fn pawn_promotes_to_every_piece() {
+        assert_eq!(
+            moves_from("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", "a7"),
+            ["a7a8b", "a7a8n", "a7a8q", "a7a8r"]
+        );
+    }
+
+    #[test]
+    fn start_position_has_queens_on_the_d_file() {
+        let mut board = Matrix::new();
+        board.populate_pieces();
+        assert_eq!(board.to_fen(), crate::perft::START_FEN);
+    }
+
+    #[test]
+    fn chess960_numbers_place_the_back_row() {
+        let names = |number: u32| -> String {
+            chess960_rank(number)
+                .iter()
+                .map(|piece| piece.letter().to_ascii_uppercase())
+                .collect()
+        };
+        assert_eq!(names(518), "RNBQKBNR");
+        assert_eq!(names(0), "BBQNNRKR");
+        assert_eq!(names(959), "RKRNNQBB");
+    }
+
+    #[test]
+    fn chess960_castling_is_the_king_onto_its_rook() {
+        let fen = "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w GBgb - 0 1";
+        let mut board = Matrix::from_fen(fen).unwrap();
+        assert!(board.chess960);
+        assert_eq!(board.to_fen(), "1r2k1r1/8/8/8/8/8/8/1R2K1R1 w KQkq - 0 1");
+        assert!(moves_from(fen, "e1").contains(&String::from("e1b1")));
+        board.make_move(Move::parse("e1g1").unwrap());
+        assert_eq!(board.placement(), "1r2k1r1/8/8/8/8/8/8/1R3RK1");
+        board.make_move(Move::parse("e8b8").unwrap());
+        assert_eq!(board.placement(), "2kr2r1/8/8/8/8/8/8/1R3RK1");
+        assert_eq!(board.to_fen(), "2kr2r1/8/8/8/8/8/8/1R3RK1 w - - 2 2");
+    }
+
+    #[test]
+    fn fairy_pieces_mix_leaps_and_slides() {
+        assert_eq!(
+            moves_from("4k3/8/8/8/8/8/8/L3K3 w - - 0 1", "a1"),
+            ["a1b4", "a1d2"]
+        );
+        assert_eq!(
+            moves_from("4k3/8/8/8/8/8/8/I3K3 w - - 0 1", "a1"),
+            ["a1b3", "a1c2", "a1c5", "a1d7", "a1e3", "a1g4"]
+        );
+        assert_eq!(
+            moves_from("4k3/8/8/8/8/8/8/A3K3 w - - 0 1", "a1").len(),
+            7 + 2
+        );
+        //the chancellor's knight jump gives check
+        let board = Matrix::from_fen("4k3/8/3C4/8/8/8/8/4K3 b - - 0 1").unwrap();
+        assert!(board.in_check(Colour::Black));
+        assert!(!board.fits_bitboards());
+        assert_eq!(
+            Matrix::from_fen(&board.to_fen()).unwrap().to_fen(),
+            board.to_fen()
+        );
+    }
+
+    //a1 is dark and in the bottom left corner seen from white, so the queen
+    //stands on the d file whichever side is at the top
+    #[test]
+    fn board_is_drawn_the_right_way_round() {
+        let (theme, _) =
+            crate::theme::select(&[String::from("--ascii")], &crate::config::Settings::new());
+        let area = crate::layout::compute(80, 24, 8, 8).unwrap().board;
+        for flipped in [false, true] {
+            let mut board = Matrix::new();
+            board.populate_pieces();
+            board.flipped = flipped;
+            let mut frame = Frame::new(80, 24);
+            board.display(&theme, &mut frame, &area, None);
+            let labels = area.y + 8 * area.square_height();
+            let find = |y: u16, text: &str| (0..80).find(|x| frame.text_at(*x, y) == text);
+            let white_queen = (area.y..labels).find_map(|y| find(y, "Q "));
+            let black_queen = (area.y..labels).find_map(|y| find(y, "q "));
+            assert_eq!(white_queen, find(labels, "d"));
+            assert_eq!(black_queen, find(labels, "d"));
+            //the a file is on the left for whoever sits at the bottom
+            assert_eq!(find(labels, "a") < find(labels, "h"), flipped);
+            let row_of = |text: &str| (area.y..labels).find(|y| find(*y, text).is_some());
+            assert_eq!(row_of("K ") > row_of("k "), flipped);
+            //clicking where a square is drawn finds it again
+            let e1 = (area.y..labels)
+                .find_map(|y| find(y, "K ").map(|x| (x, y)))
+                .unwrap();
+            assert_eq!(board.square_at(e1.0, e1.1, &area), Some((0, 4)));
+        }
+    }
+
+    #[test]
+    fn fen_round_trip() {
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b Kq e3 3 17";
+        assert_eq!(Matrix::from_fen(fen).unwrap().to_fen(), fen);
+    }
+
+    //every move of a made up game is made and taken back: the key has to match
+    //one worked out from scratch, and polyglot's on the bitboards, and taking
+    //the move back has to leave the board as it was
+    #[test]
+    fn keys_follow_make_and_unmake() {
+        let mut boards: Vec<Matrix> = crate::perft::REFERENCE_POSITIONS
+            .iter()
+            .map(|(_, fen)| Matrix::from_fen(fen).unwrap())
+            .collect();
+        for variant in variants::ALL {
+            let mut board = Matrix::empty(variant);
+            board.populate_pieces();
+            boards.push(board);
+        }
+        let mut seen = [false; 3]; //castling, en passant, promotion
+        let mut state: u64 = 1;
+        for mut board in boards {
+            for _ in 0..40 {
+                let moves = board.legal_moves();
+                if moves.is_empty() || board.variant_win().is_some() {
+                    break;
+                }
+                for candidate in &moves {
+                    let (fen, key) = (board.to_fen(), board.key);
+                    let (piece, _) = board.piece_at(candidate.from);
+                    seen[0] |= board.castling_wing(candidate.from, candidate.to).is_some();
+                    seen[1] |= piece == Piece::Pawn
+                        && candidate.from.1 != candidate.to.1
+                        && board.is_empty(candidate.to);
+                    seen[2] |= candidate.promotion.is_some();
+                    let undo = board.make_move(*candidate);
+                    assert_eq!(board.key, board.fresh_key(), "{} {}", fen, candidate.name());
+                    if board.fits_bitboards() {
+                        assert_eq!(board.hash(), Position::from(&board).hash());
+                    }
+                    board.unmake_move(undo);
+                    assert_eq!((board.to_fen(), board.key), (fen, key));
+                }
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                board.make_move(moves[(state >> 33) as usize % moves.len()]);
+            }
+        }
+        assert_eq!(seen, [true; 3]);
+    }
+
+    #[test]
+    fn squares_that_are_not_letters_are_refused() {
+        assert_eq!(parse_square("é3"), None);
+        assert_eq!(Move::parse("é2e4"), None);
+        let fen = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq é3 0 1";
+        assert!(Matrix::from_fen(fen).is_err());
+    }
+
+    #[test]
+    fn long_runs_of_empty_squares_are_refused() {
+        let fen = "rnbqkbnr/pppppppp/99999999999999999999/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        assert!(Matrix::from_fen(fen).is_err());
+        let fen = "99999999999999999999/8/8/8/8/8/8/8 w - - 0 1";
+        assert!(Matrix::from_fen(fen).is_err());
+        let fen = "4k3/8/8/8/8/8/8/4K6~ w - - 0 1";
+        assert!(Matrix::from_fen(fen).is_err());
+    }
+
+    #[test]
+    fn variants_start_on_their_own_boards() {
+        let start = |variant| {
+            let mut board = Matrix::empty(variant);
+            board.populate_pieces();
+            board.to_fen()
+        };
+        assert_eq!(
+            start(&variants::CAPABLANCA),
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1"
+        );
+        assert_eq!(
+            start(&variants::GARDNER),
+            "rnbqk/ppppp/5/PPPPP/RNBQK w - - 0 1"
+        );
+        //no double step on 6x6, and promotion only to what the variant has
+        assert_eq!(moves_from("3k2/P5/6/6/1P4/3K2 w - - 0 1", "b2"), ["b2b3"]);
+        assert_eq!(
+            moves_from("3k2/P5/6/6/1P4/3K2 w - - 0 1", "a5"),
+            ["a5a6n", "a5a6q", "a5a6r"]
+        );
+        //the king lands on i1 castling king side on 10 columns
+        let board = Matrix::from_fen("r4k3r/10/10/10/10/10/10/R4K3R w KQkq - 0 1").unwrap();
+        assert_eq!(board.variant.name, "capablanca");
+        let castles: Vec<String> = board
+            .legal_moves()
+            .iter()
+            .filter(|candidate| candidate.from == (0, 5) && candidate.to.1.abs_diff(5) > 1)
+            .map(|candidate| candidate.name())
+            .collect();
+        assert_eq!(castles, ["f1i1", "f1c1"]);
+        let mut castled = board.clone();
+        castled.make_move(Move::parse("f1i1").unwrap());
+        assert!(castled
+            .to_fen()
+            .starts_with("r4k3r/10/10/10/10/10/10/R6RK1 b kq"));
+        assert!(Matrix::from_fen("8/8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+    }
+
+    fn playing(variant: &'static Variant, fen: &str, moves: &[&str]) -> Matrix {
+        let mut board = Matrix::from_fen_in(fen, Some(variant)).unwrap();
+        for name in moves {
+            let chosen = Move::parse(name).unwrap();
+            assert!(board.legal_moves().contains(&chosen), "{}", name);
+            board.make_move(chosen);
+        }
+        board
+    }
+
+    #[test]
+    fn variants_win_their_own_way() {
+        //the knight takes on f7 and blows up the king next to it
+        let atomic = playing(
+            &variants::ATOMIC,
+            "rnbqkbnr/pppppppp/8/8/8/5N2/PPPPPPPP/RNBQKB1R w KQkq - 0 1",
+            &["f3g5", "a7a6", "g5f7"],
+        );
+        assert_eq!(atomic.variant_win(), Some(Colour::White));
+        assert_eq!(atomic.piece_at((6, 6)).0, Piece::Pawn);
+        assert_eq!(atomic.piece_at((7, 6)).0, Piece::Null);
+        assert_eq!(atomic.piece_at((7, 3)).0, Piece::Queen);
+        //a king can't take, and kings side by side don't give check
+        let kings = playing(&variants::ATOMIC, "8/8/8/3kq3/4K3/8/8/8 w - - 0 1", &[]);
+        assert!(!kings.in_check(Colour::White));
+        assert!(!kings.legal_moves().iter().any(|chosen| chosen.to == (4, 4)));
+
+        let hill = playing(
+            &variants::KING_OF_THE_HILL,
+            "4k3/8/8/8/8/4K3/8/8 w - - 0 1",
+            &["e3e4"],
+        );
+        assert_eq!(hill.variant_win(), Some(Colour::White));
+
+        let checks = playing(
+            &variants::THREE_CHECK,
+            "4k3/8/8/8/8/8/8/R3K3 w - - 0 1",
+            &["a1a8", "e8e7", "a8a7", "e7e6", "a7a6"],
+        );
+        assert_eq!(checks.checks, [3, 0]);
+        assert_eq!(checks.variant_win(), Some(Colour::White));
+        let fen = checks.to_fen();
+        assert!(fen.ends_with(" +3+0"));
+        assert_eq!(Matrix::from_fen(&fen).unwrap().checks, [3, 0]);
+    }
+
+    #[test]
+    fn crazyhouse_pockets_and_drops() {
+        //the promoted queen on d8 goes into the pocket as a pawn
+        let mut board = playing(
+            &variants::CRAZYHOUSE,
+            "3Q~k3/8/8/8/8/8/8/4K2R[n] b K - 0 1",
+            &["e8d8"],
+        );
+        assert_eq!(board.pockets, [[0; 5], [0, 0, 0, 1, 1]]);
+        assert!(board.to_fen().starts_with("3k4/8/8/8/8/8/8/4K2R[np] w K"));
+        let fen = board.to_fen();
+        let mut again = Matrix::from_fen(&fen).unwrap();
+        again.variant = &variants::CRAZYHOUSE;
+        assert_eq!(again.to_fen(), fen);
+
+        board.make_move(Move::parse("h1h2").unwrap());
+        assert_eq!(board.next_in_pocket(None), Some(Piece::Knight));
+        assert_eq!(board.next_in_pocket(Some(Piece::Knight)), Some(Piece::Pawn));
+        assert_eq!(board.next_in_pocket(Some(Piece::Pawn)), None);
+        board.arrow = (0, 0);
+        assert_eq!(
+            board.drop_piece(Piece::Pawn),
+            Err(MoveError::PawnOnEdgeRank)
+        );
+        board.arrow = (1, 7);
+        assert_eq!(board.drop_piece(Piece::Knight), Err(MoveError::SquareTaken));
+        board.arrow = (2, 5);
+        assert_eq!(board.drop_piece(Piece::Knight).unwrap().name(), "N@f3");
+        assert!(board.in_check(Colour::White));
+    }
+
+    #[test]
+    fn antichess_forces_captures() {
+        let board = playing(
+            &variants::ANTICHESS,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            &["e2e4", "d7d5"],
+        );
+        let names: Vec<String> = board.legal_moves().iter().map(Move::name).collect();
+        assert_eq!(names, ["e4d5"]);
+        let mut board = board;
+        board.arrow = (2, 0);
+        assert_eq!(board.movement((1, 0)), Err(MoveError::MustCapture));
+
+        //the king can be taken, and the side left with nothing wins
+        let fen = "8/8/8/8/8/8/3k4/4K3 w - - 0 1";
+        let mut board = Matrix::from_fen_in(fen, Some(&variants::ANTICHESS)).unwrap();
+        assert!(!board.in_check(Colour::White));
+        board.make_move(Move::parse("e1d2").unwrap());
+        assert_eq!(board.variant_win(), Some(Colour::Black));
+        assert!(Matrix::from_fen(&board.to_fen()).is_err());
+        assert!(Matrix::from_fen_in(&board.to_fen(), Some(&variants::ANTICHESS)).is_ok());
+    }
+
+    #[test]
+    fn horde_is_lost_with_its_last_pawn() {
+        let fen = "4k3/8/8/8/8/8/3q4/2P5 b - - 0 1";
+        assert!(Matrix::from_fen(fen).is_err());
+        let board = playing(&variants::HORDE, fen, &[]);
+        assert_eq!(board.variant_win(), None);
+        let board = playing(&variants::HORDE, fen, &["d2c1"]);
+        assert_eq!(board.variant_win(), Some(Colour::Black));
+    }
+
+    #[test]
+    fn fog_of_war_hides_and_takes_kings() {
+        let start = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+        let seen = playing(&variants::FOG_OF_WAR, start, &[]).visible(Colour::White);
+        assert!(seen[0][4] && seen[2][5] && seen[3][4]);
+        assert!(!seen[4][4] && !seen[6][0]);
+
+        //the king may be left hanging, and taking it wins
+        let board = playing(&variants::FOG_OF_WAR, start, &["e2e4", "f7f6", "d1h5"]);
+        assert!(board.visible(Colour::White)[7][4]);
+        assert!(!board.in_check(Colour::Black));
+        let board = playing(&variants::FOG_OF_WAR, &board.to_fen(), &["a7a6", "h5e8"]);
+        assert_eq!(board.variant_win(), Some(Colour::White));
+    }
+
+    fn attempt(fen: &str, chosen: &str) -> Result<Move, MoveError> {
+        let mut board = Matrix::from_fen(fen).unwrap();
+        let chosen = Move::parse(chosen).unwrap();
+        board.arrow = chosen.to;
+        board.movement(chosen.from)
+    }
+
+    #[test]
+    fn rejected_moves_say_why() {
+        let start = crate::perft::START_FEN;
+        assert_eq!(attempt(start, "a1a3"), Err(MoveError::PathBlocked));
+        assert_eq!(attempt(start, "e2d3"), Err(MoveError::NothingToTake));
+        assert_eq!(
+            attempt(start, "e7e5"),
+            Err(MoveError::NotYourTurn(Colour::White))
+        );
+        assert_eq!(attempt(start, "d1d2"), Err(MoveError::OwnPieceOnTarget));
+        assert_eq!(
+            attempt(start, "g1g3"),
+            Err(MoveError::IllegalPieceMove(Piece::Knight))
+        );
+        assert_eq!(
+            attempt("4k3/8/8/8/8/8/4r3/R3K2R w - - 0 1", "e1g1"),
+            Err(MoveError::CastlingNotAllowed)
+        );
+        assert_eq!(
+            attempt("4k3/8/8/8/8/8/5r2/R3K2R w KQ - 0 1", "e1g1"),
+            Err(MoveError::CastlesThroughCheck)
+        );
+        assert_eq!(
+            attempt("4k3/4r3/8/8/8/8/4B3/4K3 w - - 0 1", "e2d3"),
+            Err(MoveError::LeavesKingInCheck)
+        );
+        assert!(attempt(start, "e2e4").is_ok());
     }
 }
@@ -0,0 +1,188 @@
+use crate::board::{Matrix, Move, Piece};
+use crate::config::Settings;
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::path::Path;
+
+//one 16 byte record of a polyglot book, all big endian
+#[derive(Clone, Copy)]
+struct Entry {
+    key: u64,
+    raw: u16, //to file, to rank, from file, from rank, promotion, three bits each
+    weight: u16,
+}
+
+//a polyglot .bin opening book, entries sorted by the zobrist key of the position
+pub struct Book {
+    entries: Vec<Entry>,
+}
+
+impl Book {
+    //the book file named in the settings. the default file is optional, a missing
+    //one named on purpose or a broken file is reported and there is no book
+    pub fn load(settings: &Settings) -> (Option<Book>, Option<String>) {
+        let path = match settings.book_path() {
+            Some(path) => path,
+            None => return (None, None),
+        };
+        if !path.exists() {
+            if settings.book == Settings::new().book {
+                return (None, None);
+            }
+            return (None, Some(format!("{}: file not found", path.display())));
+        }
+        match Book::open(&path) {
+            Ok(book) => (Some(book), None),
+            Err(error) => (None, Some(error)),
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Book, String> {
+        let bytes = fs::read(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Book::from_bytes(&bytes).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Book, String> {
+        if !bytes.len().is_multiple_of(16) {
+            return Err(String::from(
+                "not a polyglot book, the size isn't a multiple of 16 bytes",
+            ));
+        }
+        let mut entries: Vec<Entry> = bytes
+            .chunks_exact(16)
+            .map(|record| Entry {
+                key: u64::from_be_bytes(record[0..8].try_into().unwrap()),
+                raw: u16::from_be_bytes([record[8], record[9]]),
+                weight: u16::from_be_bytes([record[10], record[11]]),
+            })
+            .collect();
+        //books are written sorted, this only costs anything for one that isn't
+        if !entries.windows(2).all(|pair| pair[0].key <= pair[1].key) {
+            entries.sort_by_key(|entry| entry.key);
+        }
+        Ok(Book { entries })
+    }
+
+    //the book moves for the position with their weights, heaviest first. moves
+    //that aren't legal here (a key collision or a broken book) are left out
+    pub fn moves(&self, board: &Matrix) -> Vec<(Move, u16)> {
+        let key = board.hash();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let mut moves: Vec<(Move, u16)> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .map(|entry| (decode(board, entry.raw), entry.weight))
+            .collect();
+        //out of the book there is nothing to check, so no move list is made
+        if !moves.is_empty() {
+            let legal = board.legal_moves();
+            moves.retain(|(chosen, _)| legal.contains(chosen));
+        }
+        moves.sort_by_key(|(_, weight)| u16::MAX - weight);
+        moves
+    }
+
+    //a book move picked at random, the heavier ones more often. None once out of book
+    pub fn pick(&self, board: &Matrix) -> Option<Move> {
+        let moves = self.moves(board);
+        let total: u64 = moves.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            //all zero weights still means the moves are in the book
+            return moves.first().map(|(chosen, _)| *chosen);
+        }
+        let mut roll = random() % total;
+        for (chosen, weight) in moves {
+            if roll < weight as u64 {
+                return Some(chosen);
+            }
+            roll -= weight as u64;
+        }
+        None
+    }
+}
+
+//polyglot squares count a1 = 0 up to h8 = 63 like the board rows here, and
+//castling is written as the king taking its own rook, which is how chess960
+//boards write it anyway
+fn decode(board: &Matrix, raw: u16) -> Move {
+    let square = |bits: u16| (((bits >> 3) & 7) as usize, (bits & 7) as usize);
+    let from = square(raw >> 6);
+    let mut to = square(raw);
+    let (piece, colour) = board.piece_at(from);
+    if piece == Piece::King && board.piece_at(to) == (Piece::Rook, colour) && !board.chess960 {
+        to.1 = if to.1 > from.1 { 6 } else { 2 };
+    }
+    let promotion = match (raw >> 12) & 7 {
+        1 => Some(Piece::Knight),
+        2 => Some(Piece::Bishop),
+        3 => Some(Piece::Rook),
+        4 => Some(Piece::Queen),
+        _ => None,
+    };
+    Move {
+        from,
+        to,
+        promotion,
+        drop: None,
+    }
+}
+
+//good enough to vary the openings, std hashers are seeded randomly every time
+pub fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(key: u64, raw: u16, weight: u16) -> Vec<u8> {
+        let mut bytes = key.to_be_bytes().to_vec();
+        bytes.extend(raw.to_be_bytes());
+        bytes.extend(weight.to_be_bytes());
+        bytes.extend([0; 4]);
+        bytes
+    }
+
+    //from and to squares as polyglot writes them, a1 = 0
+    fn raw(from: u16, to: u16) -> u16 {
+        from << 6 | to
+    }
+
+    #[test]
+    fn finds_weighted_moves_for_the_start_position() {
+        let board = Matrix::from_fen(crate::perft::START_FEN).unwrap();
+        let mut bytes = record(board.hash(), raw(11, 27), 10); //d2d4
+        bytes.extend(record(board.hash(), raw(12, 28), 30)); //e2e4
+        bytes.extend(record(board.hash(), raw(12, 36), 50)); //e2e5 isn't legal
+        bytes.extend(record(board.hash() + 1, raw(6, 21), 50)); //some other position
+        let book = Book::from_bytes(&bytes).unwrap();
+        let names: Vec<(String, u16)> = book
+            .moves(&board)
+            .iter()
+            .map(|(chosen, weight)| (chosen.name(), *weight))
+            .collect();
+        assert_eq!(
+            names,
+            [(String::from("e2e4"), 30), (String::from("d2d4"), 10)]
+        );
+        let picked = book.pick(&board).unwrap().name();
+        assert!(picked == "e2e4" || picked == "d2d4");
+    }
+
+    #[test]
+    fn castling_is_the_king_taking_its_rook() {
+        let board = Matrix::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1").unwrap();
+        let mut bytes = record(board.hash(), raw(4, 7), 1);
+        bytes.extend(record(board.hash(), raw(4, 0), 1));
+        let book = Book::from_bytes(&bytes).unwrap();
+        let mut names: Vec<String> = book
+            .moves(&board)
+            .iter()
+            .map(|(chosen, _)| chosen.name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["e1c1", "e1g1"]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::board::Colour;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+//minutes each player starts with and seconds added after every move, written 5+3
+#[derive(Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub minutes: u32,
+    pub increment: u32,
+}
+
+impl TimeControl {
+    //the choices offered in the settings screen
+    pub const PRESETS: [TimeControl; 6] = [
+        TimeControl::new(1, 0),
+        TimeControl::new(3, 2),
+        TimeControl::new(5, 0),
+        TimeControl::new(10, 0),
+        TimeControl::new(15, 10),
+        TimeControl::new(30, 0),
+    ];
+
+    pub const fn new(minutes: u32, increment: u32) -> TimeControl {
+        TimeControl { minutes, increment }
+    }
+
+    pub fn parse(text: &str) -> Option<TimeControl> {
+        let (minutes, increment) = text.split_once('+')?;
+        let minutes = minutes.trim().parse().ok()?;
+        let increment = increment.trim().parse().ok()?;
+        if minutes == 0 {
+            return None;
+        }
+        Some(TimeControl::new(minutes, increment))
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}+{}", self.minutes, self.increment)
+    }
+}
+
+//a chess clock, only the side to move has their time running
+pub struct Clock {
+    control: TimeControl,
+    remaining: [Duration; 2],
+    increment: Duration,
+    running: Colour,
+    since: Instant,
+}
+
+impl Clock {
+    //white's time starts running straight away
+    pub fn new(control: TimeControl) -> Clock {
+        let start = Duration::from_secs(control.minutes as u64 * 60);
+        Clock::resume(control, [start, start], Colour::White)
+    }
+
+    //a clock from a saved game, white's time first
+    pub fn resume(control: TimeControl, remaining: [Duration; 2], running: Colour) -> Clock {
+        Clock {
+            control,
+            remaining,
+            increment: Duration::from_secs(control.increment as u64),
+            running,
+            since: Instant::now(),
+        }
+    }
+
+    pub fn control(&self) -> TimeControl {
+        self.control
+    }
+
+    //called once a move has been made, the mover gets their increment
+    //and the clock switches to the other side
+    pub fn press(&mut self) {
+        let mover = index(self.running);
+        self.remaining[mover] = self.remaining(self.running) + self.increment;
+        self.running = if self.running == Colour::White {
+            Colour::Black
+        } else {
+            Colour::White
+        };
+        self.since = Instant::now();
+    }
+
+    pub fn remaining(&self, colour: Colour) -> Duration {
+        let stored = self.remaining[index(colour)];
+        if colour == self.running {
+            stored.saturating_sub(self.since.elapsed())
+        } else {
+            stored
+        }
+    }
+
+    //the side whose time ran out, if any
+    pub fn flagged(&self) -> Option<Colour> {
+        if self.remaining(self.running).is_zero() {
+            Some(self.running)
+        } else {
+            None
+        }
+    }
+}
+
+fn index(colour: Colour) -> usize {
+    (colour == Colour::Black) as usize
+}
+
+//m:ss, with tenths of a second once under ten seconds
+pub fn format_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds < 10 {
+        format!("0:0{}.{}", seconds, time.subsec_millis() / 100)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
@@ -0,0 +1,287 @@
+use crate::clock::TimeControl;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//$XDG_CONFIG_HOME/ascii_chess, falling back to ~/.config/ascii_chess or
+//%APPDATA%\ascii_chess on windows. None if there is no home to put it in
+pub fn config_dir() -> Option<PathBuf> {
+    let base = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join("ascii_chess"))
+}
+
+//strongest level the computer opponent can be set to
+pub const MAX_AI_LEVEL: u8 = 5;
+
+//everything that is remembered between runs, stored in settings.conf
+#[derive(Clone)]
+pub struct Settings {
+    pub theme: String,
+    pub pieces: String, //unicode, ascii, or auto to go by the locale
+    pub keymap: String, //keymap file, relative to the config folder
+    pub book: String,   //polyglot opening book, relative to the config folder, or none
+    pub time_control: Option<TimeControl>,
+    pub ai_level: u8,
+    pub flipped: bool,
+    pub bell: bool,
+}
+
+impl Settings {
+    pub fn new() -> Settings {
+        Settings {
+            theme: String::from("classic"),
+            pieces: String::from("auto"),
+            keymap: String::from("keymap.conf"),
+            book: String::from("book.bin"),
+            time_control: None,
+            ai_level: 3,
+            flipped: false,
+            bell: true,
+        }
+    }
+
+    //reads settings.conf from the config folder. anything that can't be understood
+    //is reported and left at its default, a missing file just means all defaults
+    pub fn load() -> (Settings, Vec<String>) {
+        let settings = Settings::new();
+        let path = match settings_path() {
+            Some(path) => path,
+            None => return (settings, Vec::new()),
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => Settings::parse(&text, &path),
+            Err(_) if !path.exists() => (settings, Vec::new()),
+            Err(error) => (settings, vec![format!("{}: {}", path.display(), error)]),
+        }
+    }
+
+    //the settings in the text of a settings file, `path` is only for the errors
+    pub fn parse(text: &str, path: &Path) -> (Settings, Vec<String>) {
+        let mut settings = Settings::new();
+        let mut errors = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let result = match setting_line(line) {
+                Some(Some((key, value))) => settings.set(key, value),
+                Some(None) => Err(String::from("expected `key = value`")),
+                None => continue,
+            };
+            if let Err(error) = result {
+                errors.push(format!("{}:{}: {}", path.display(), number + 1, error));
+            }
+        }
+        (settings, errors)
+    }
+
+    fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "theme" => self.theme = String::from(value),
+            "pieces" => match value {
+                "auto" | "unicode" | "ascii" => self.pieces = String::from(value),
+                _ => {
+                    return Err(format!(
+                        "pieces must be auto, unicode or ascii, not `{}`",
+                        value
+                    ))
+                }
+            },
+            "keymap" => self.keymap = String::from(value),
+            "book" => self.book = String::from(value),
+            "time_control" => {
+                self.time_control = match value {
+                    "none" => None,
+                    _ => Some(TimeControl::parse(value).ok_or_else(|| {
+                        format!(
+                            "time_control must be none or minutes+increment like 5+3, not `{}`",
+                            value
+                        )
+                    })?),
+                }
+            }
+            "ai_level" => {
+                self.ai_level = value
+                    .parse()
+                    .ok()
+                    .filter(|level| (1..=MAX_AI_LEVEL).contains(level))
+                    .ok_or_else(|| {
+                        format!("ai_level must be 1 to {}, not `{}`", MAX_AI_LEVEL, value)
+                    })?
+            }
+            "orientation" => {
+                self.flipped = match value {
+                    "white-top" => false,
+                    "black-top" => true,
+                    _ => {
+                        return Err(format!(
+                            "orientation must be white-top or black-top, not `{}`",
+                            value
+                        ))
+                    }
+                }
+            }
+            "bell" => {
+                self.bell = match value {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(format!("bell must be on or off, not `{}`", value)),
+                }
+            }
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+
+    //writes the settings back to settings.conf, creating the config folder if
+    //needed. only lines whose value changed are rewritten, so comments and the
+    //order of the file are kept
+    pub fn save(&self) -> Result<(), String> {
+        let path = settings_path().ok_or("no config folder to save settings in")?;
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("{}: {}", directory.display(), error))?;
+        }
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        fs::write(&path, self.updated(&text))
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    //the text of a settings file with these settings in it. settings the file
+    //doesn't have yet are added at the end
+    fn updated(&self, text: &str) -> String {
+        let values = self.values();
+        let mut written = [false; 8];
+        let mut lines = Vec::new();
+        for line in text.lines() {
+            let slot = setting_line(line).flatten().and_then(|(key, value)| {
+                Some((values.iter().position(|(known, _)| *known == key)?, value))
+            });
+            match slot {
+                Some((slot, value)) if value != values[slot].1 => {
+                    lines.push(format!("{} = {}", values[slot].0, values[slot].1));
+                    written[slot] = true;
+                }
+                Some((slot, _)) => {
+                    lines.push(String::from(line));
+                    written[slot] = true;
+                }
+                None => lines.push(String::from(line)),
+            }
+        }
+        for ((key, value), _) in values.iter().zip(written).filter(|(_, written)| !written) {
+            lines.push(format!("{} = {}", key, value));
+        }
+        lines.join("\n") + "\n"
+    }
+
+    //every setting as it is written in the file
+    fn values(&self) -> [(&'static str, String); 8] {
+        let time_control = match self.time_control {
+            Some(control) => control.to_string(),
+            None => String::from("none"),
+        };
+        [
+            ("theme", self.theme.clone()),
+            ("pieces", self.pieces.clone()),
+            ("keymap", self.keymap.clone()),
+            ("book", self.book.clone()),
+            ("time_control", time_control),
+            ("ai_level", self.ai_level.to_string()),
+            (
+                "orientation",
+                String::from(if self.flipped {
+                    "black-top"
+                } else {
+                    "white-top"
+                }),
+            ),
+            ("bell", String::from(if self.bell { "on" } else { "off" })),
+        ]
+    }
+
+    //the keymap file, relative paths are looked up in the config folder
+    pub fn keymap_path(&self) -> Option<PathBuf> {
+        in_config_dir(&self.keymap)
+    }
+
+    //the opening book the computer plays from, None when it is turned off
+    pub fn book_path(&self) -> Option<PathBuf> {
+        if self.book == "none" {
+            return None;
+        }
+        in_config_dir(&self.book)
+    }
+}
+
+fn in_config_dir(name: &str) -> Option<PathBuf> {
+    let path = PathBuf::from(name);
+    if path.is_absolute() {
+        return Some(path);
+    }
+    Some(config_dir()?.join(path))
+}
+
+fn settings_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("settings.conf"))
+}
+
+//None for blank lines and comments, which are whole lines starting with # like
+//in the theme and keymap files, so a value can still have a # in it. Some(None)
+//for a line that isn't `key = value`
+fn setting_line(line: &str) -> Option<Option<(&str, &str)>> {
+    let line = line.trim();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    Some(
+        line.split_once('=')
+            .map(|(key, value)| (key.trim(), value.trim())),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_file_is_read_line_by_line() {
+        let text = "# my settings\n\nbook = books/#1.bin\nai_level = 9\nsyzygy = none\nbell off\norientation = black-top\n";
+        let (settings, errors) = Settings::parse(text, Path::new("settings.conf"));
+        assert_eq!(settings.book, "books/#1.bin");
+        assert!(settings.flipped);
+        assert_eq!(settings.ai_level, Settings::new().ai_level);
+        assert_eq!(
+            errors,
+            [
+                "settings.conf:4: ai_level must be 1 to 5, not `9`",
+                "settings.conf:5: unknown setting `syzygy`",
+                "settings.conf:6: expected `key = value`",
+            ]
+        );
+    }
+
+    #[test]
+    fn saving_only_rewrites_changed_lines() {
+        let text =
+            "# picked by hand\nbell = off\n\n  theme =   classic\n# stronger\nai_level = 2\n";
+        let (mut settings, _) = Settings::parse(text, Path::new("settings.conf"));
+        settings.ai_level = 4;
+        let saved = settings.updated(text);
+        let lines: Vec<&str> = saved.lines().collect();
+        assert_eq!(
+            lines[..6],
+            [
+                "# picked by hand",
+                "bell = off",
+                "",
+                "  theme =   classic",
+                "# stronger",
+                "ai_level = 4",
+            ]
+        );
+        assert_eq!(lines[6..].len(), 5);
+        assert!(lines.contains(&"orientation = white-top"));
+        assert_eq!(settings.updated(&saved), saved);
+    }
+}
@@ -0,0 +1,449 @@
+use crate::board::{Colour, Matrix, Move};
+use crate::clock::{Clock, TimeControl};
+use crate::config::{self, Settings, MAX_AI_LEVEL};
+use crate::setups::Setup;
+use crate::variants::{self, Variant};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+//who is playing a side, the computer plays at one of the levels from the settings
+#[derive(Clone, Copy, PartialEq)]
+pub enum Player {
+    Human,
+    Computer(u8),
+}
+
+impl Player {
+    //written as human or computer-3 in saved games
+    pub fn name(&self) -> String {
+        match self {
+            Player::Human => String::from("human"),
+            Player::Computer(level) => format!("computer-{}", level),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Player> {
+        if text == "human" {
+            return Some(Player::Human);
+        }
+        let level = text.strip_prefix("computer-")?.parse().ok()?;
+        if !(1..=MAX_AI_LEVEL).contains(&level) {
+            return None;
+        }
+        Some(Player::Computer(level))
+    }
+}
+
+//a game in progress, everything needed to put it away and pick it up later
+pub struct Game {
+    pub board: Matrix,
+    pub moves: Vec<String>,  //every move so far, from and to square like e2e4
+    pub positions: Vec<u64>, //zobrist key of every position so far, the current one last
+    pub clock: Option<Clock>,
+    pub players: [Player; 2],  //white then black
+    pub chess960: Option<u32>, //which chess960 start position the game began from
+    //FEN of the position the moves were played from, None in games saved before
+    //it was kept
+    pub start: Option<String>,
+}
+
+impl Game {
+    //the starting position with the clock and orientation from the settings,
+    //players are white then black. chess960 games start from the numbered position
+    pub fn new(settings: &Settings, players: [Player; 2], chess960: Option<u32>) -> Game {
+        let mut board = Matrix::new(); //generates board with no pieces
+        match chess960 {
+            Some(number) => board.populate_chess960(number),
+            None => board.populate_pieces(), //populates board with pieces
+        }
+        let mut game = Game::from_board(settings, players, board);
+        game.chess960 = chess960;
+        game
+    }
+
+    //the start position of a variant, which can be played on another size of board
+    pub fn of_variant(
+        settings: &Settings,
+        players: [Player; 2],
+        variant: &'static Variant,
+    ) -> Game {
+        let mut board = Matrix::empty(variant);
+        board.populate_pieces();
+        Game::from_board(settings, players, board)
+    }
+
+    //the start position of a variant with the odds taken off
+    pub fn of_setup(settings: &Settings, players: [Player; 2], setup: &Setup) -> Game {
+        let mut board = Matrix::empty(setup.variant);
+        board.populate_pieces();
+        board.remove_pieces(setup.removed);
+        Game::from_board(settings, players, board)
+    }
+
+    //a game from any position, a custom setup or one read from a FEN
+    pub fn from_board(settings: &Settings, players: [Player; 2], mut board: Matrix) -> Game {
+        board.flipped = settings.flipped;
+        Game {
+            positions: vec![board.hash()],
+            start: Some(board.to_fen()),
+            board,
+            moves: Vec::new(),
+            clock: settings.time_control.map(Clock::new),
+            players,
+            chess960: None,
+        }
+    }
+
+    //called after a move went through, so it is remembered and the clock switches sides
+    pub fn record(&mut self, played: Move) {
+        self.moves.push(played.name());
+        self.positions.push(self.board.hash());
+        if let Some(clock) = &mut self.clock {
+            clock.press();
+        }
+    }
+
+    //who plays the side whose turn it is
+    pub fn to_move(&self) -> Player {
+        self.players[(self.board.turn == Colour::Black) as usize]
+    }
+
+    //how many times the current position has come up, three is a draw
+    pub fn repetitions(&self) -> usize {
+        let current = self.board.hash();
+        self.positions.iter().filter(|key| **key == current).count()
+    }
+
+    //one `key = value` per line, the same layout as the other config files
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut text = String::from("# ascii chess saved game\n");
+        if self.board.variant.name != variants::STANDARD.name {
+            text.push_str(&format!("variant = {}\n", self.board.variant.name));
+        }
+        if let Some(start) = &self.start {
+            text.push_str(&format!("start = {}\n", start));
+        }
+        text.push_str(&format!("position = {}\n", self.board.to_fen()));
+        if let Some(number) = self.chess960 {
+            text.push_str(&format!("chess960 = {}\n", number));
+        }
+        text.push_str(&format!("key = {:016x}\n", self.board.hash()));
+        text.push_str(&format!("moves = {}\n", self.moves.join(" ")));
+        //positions since the last capture or pawn move are all that can repeat
+        let current = self.positions.len().saturating_sub(1);
+        let since = current.saturating_sub(self.board.halfmove as usize);
+        let history: Vec<String> = self.positions[since..current]
+            .iter()
+            .map(|key| format!("{:016x}", key))
+            .collect();
+        text.push_str(&format!("history = {}\n", history.join(" ")));
+        text.push_str(&format!("white = {}\n", self.players[0].name()));
+        text.push_str(&format!("black = {}\n", self.players[1].name()));
+        text.push_str(&format!(
+            "orientation = {}\n",
+            if self.board.flipped {
+                "black-top"
+            } else {
+                "white-top"
+            }
+        ));
+        if let Some(clock) = &self.clock {
+            text.push_str(&format!("time_control = {}\n", clock.control()));
+            text.push_str(&format!(
+                "white_time = {}\n",
+                clock.remaining(Colour::White).as_millis()
+            ));
+            text.push_str(&format!(
+                "black_time = {}\n",
+                clock.remaining(Colour::Black).as_millis()
+            ));
+        }
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory)
+                .map_err(|error| format!("{}: {}", directory.display(), error))?;
+        }
+        fs::write(path, text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    //the whole file has to make sense, a half loaded game is no use to anyone
+    pub fn load(path: &Path) -> Result<Game, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        let mut game = Game {
+            board: Matrix::new(),
+            moves: Vec::new(),
+            positions: Vec::new(),
+            clock: None,
+            players: [Player::Human, Player::Human],
+            chess960: None,
+            start: None,
+        };
+        //read once the variant is known, which may come after them
+        let mut position: Option<(usize, &str)> = None;
+        let mut start: Option<(usize, &str)> = None;
+        let mut variant = None;
+        let mut position_key = None;
+        let mut flipped = false;
+        let mut control = None;
+        let mut times = [None, None];
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path.display(), number + 1, message);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error(String::from("expected `key = value`")))?;
+            let value = value.trim();
+            match key.trim() {
+                "position" => position = Some((number + 1, value)),
+                "start" => start = Some((number + 1, value)),
+                "key" => {
+                    position_key = Some(
+                        u64::from_str_radix(value, 16)
+                            .map_err(|_| error(format!("bad key `{}`", value)))?,
+                    )
+                }
+                "history" => {
+                    for key in value.split_whitespace() {
+                        let key = u64::from_str_radix(key, 16)
+                            .map_err(|_| error(format!("bad key `{}`", key)))?;
+                        game.positions.push(key);
+                    }
+                }
+                "moves" => {
+                    for name in value.split_whitespace() {
+                        let played = Move::parse(name)
+                            .ok_or_else(|| error(format!("bad move `{}`", name)))?;
+                        game.moves.push(played.name());
+                    }
+                }
+                "white" | "black" => {
+                    let player = Player::parse(value)
+                        .ok_or_else(|| error(format!("unknown player `{}`", value)))?;
+                    game.players[(key.trim() == "black") as usize] = player;
+                }
+                "orientation" => {
+                    flipped = match value {
+                        "white-top" => false,
+                        "black-top" => true,
+                        _ => {
+                            return Err(error(format!(
+                                "orientation must be white-top or black-top, not `{}`",
+                                value
+                            )))
+                        }
+                    }
+                }
+                "variant" => {
+                    variant = Some(
+                        variants::by_name(value)
+                            .ok_or_else(|| error(format!("unknown variant `{}`", value)))?,
+                    )
+                }
+                "chess960" => {
+                    game.chess960 = Some(
+                        parse_chess960(value)
+                            .ok_or_else(|| error(format!("bad chess960 position `{}`", value)))?,
+                    )
+                }
+                "time_control" => {
+                    control = Some(
+                        TimeControl::parse(value)
+                            .ok_or_else(|| error(format!("bad time control `{}`", value)))?,
+                    )
+                }
+                "white_time" | "black_time" => {
+                    let millis: u64 = value
+                        .parse()
+                        .map_err(|_| error(format!("bad time `{}`", value)))?;
+                    times[(key.trim() == "black_time") as usize] =
+                        Some(Duration::from_millis(millis));
+                }
+                other => return Err(error(format!("unknown key `{}`", other))),
+            }
+        }
+        //the FEN only gives the size of the board, the variant says which rules
+        let (line, fen) =
+            position.ok_or_else(|| format!("{}: no position in the file", path.display()))?;
+        game.board = Matrix::from_fen_in(fen, variant)
+            .map_err(|message| format!("{}:{}: {}", path.display(), line, message))?;
+        game.board.flipped = flipped;
+        if let Some((line, fen)) = start {
+            Matrix::from_fen_in(fen, variant)
+                .map_err(|message| format!("{}:{}: {}", path.display(), line, message))?;
+            game.start = Some(String::from(fen));
+        }
+        //once nobody can castle the FEN alone doesn't say it is chess960
+        game.board.chess960 |= game.chess960.is_some();
+        //the key is a check that the position wasn't changed by hand into something else
+        if position_key.is_some_and(|key| key != game.board.hash()) {
+            return Err(format!(
+                "{}: the key does not match the position",
+                path.display()
+            ));
+        }
+        game.positions.push(game.board.hash());
+        if let Some(control) = control {
+            let start = Duration::from_secs(control.minutes as u64 * 60);
+            let remaining = [times[0].unwrap_or(start), times[1].unwrap_or(start)];
+            game.clock = Some(Clock::resume(control, remaining, game.board.turn));
+        }
+        Ok(game)
+    }
+}
+
+//a chess960 start position number, 0 to 959
+pub fn parse_chess960(text: &str) -> Option<u32> {
+    text.parse().ok().filter(|number| *number < 960)
+}
+
+//written every time a game is left unfinished, so quitting by accident loses nothing
+pub fn autosave_path() -> Option<PathBuf> {
+    Some(config::config_dir()?.join("autosave.game"))
+}
+
+//games saved on purpose, oldest first
+pub fn saved_games() -> Vec<PathBuf> {
+    let directory = match config::config_dir() {
+        Some(directory) => directory.join("saves"),
+        None => return Vec::new(),
+    };
+    let mut files: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "game")
+            })
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    files.sort_by_key(|path| save_number(path));
+    files
+}
+
+//saves/game-1.game, game-2.game, ... the first number that isn't taken yet
+pub fn new_save_path() -> Option<PathBuf> {
+    let directory = config::config_dir()?.join("saves");
+    let next = saved_games()
+        .iter()
+        .filter_map(|path| save_number(path))
+        .max()
+        .unwrap_or(0)
+        + 1;
+    Some(directory.join(format!("game-{}.game", next)))
+}
+
+fn save_number(path: &Path) -> Option<u32> {
+    path.file_stem()?
+        .to_str()?
+        .strip_prefix("game-")?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::setups;
+
+    //saved to the temp folder and read back, `name` keeps tests running at the
+    //same time apart
+    fn reload(game: &Game, name: &str) -> Game {
+        let path = std::env::temp_dir().join(format!("ascii_chess_{}.game", name));
+        game.save(&path).unwrap();
+        let loaded = Game::load(&path);
+        let _ = fs::remove_file(&path);
+        loaded.unwrap()
+    }
+
+    fn play_first_moves(game: &mut Game, count: usize) {
+        for _ in 0..count {
+            let chosen = game.board.legal_moves()[0];
+            game.board.make_move(chosen);
+            game.record(chosen);
+        }
+    }
+
+    #[test]
+    fn a_game_in_progress_loads_back() {
+        let mut settings = Settings::new();
+        settings.flipped = true;
+        let mut game = Game::new(&settings, [Player::Human, Player::Computer(4)], None);
+        //the knights go out and back, so the position after e5 comes up twice
+        for name in ["e2e4", "e7e5", "g1f3", "g8f6", "f3g1", "f6g8"] {
+            let chosen = Move::parse(name).unwrap();
+            game.board.make_move(chosen);
+            game.record(chosen);
+        }
+        let control = TimeControl::new(5, 3);
+        let times = [
+            Duration::from_millis(123_456),
+            Duration::from_millis(234_567),
+        ];
+        game.clock = Some(Clock::resume(control, times, game.board.turn));
+
+        let loaded = reload(&game, "in_progress");
+        assert_eq!(loaded.board.to_fen(), game.board.to_fen());
+        assert_eq!(loaded.moves, game.moves);
+        assert!(loaded.players == game.players);
+        assert!(loaded.board.flipped);
+        //only the positions since the last pawn move are kept, nothing before can repeat
+        assert_eq!(loaded.positions, game.positions[2..]);
+        assert_eq!(loaded.repetitions(), 2);
+        let clock = loaded.clock.unwrap();
+        assert!(clock.control() == control);
+        assert_eq!(clock.remaining(Colour::Black), times[1]);
+        //white's time ran on while the game was saved
+        let white = clock.remaining(Colour::White);
+        assert!(white <= times[0] && times[0] - white < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn bad_values_are_refused() {
+        let game = Game::new(&Settings::new(), [Player::Human, Player::Human], None);
+        let path = std::env::temp_dir().join("ascii_chess_bad_values.game");
+        game.save(&path).unwrap();
+        let text = fs::read_to_string(&path).unwrap();
+        let line = text
+            .lines()
+            .position(|line| line.starts_with("orientation"))
+            .unwrap();
+        fs::write(&path, text.replace("white-top", "sideways")).unwrap();
+        let loaded = Game::load(&path);
+        let _ = fs::remove_file(&path);
+        assert_eq!(
+            loaded.err().unwrap(),
+            format!(
+                "{}:{}: orientation must be white-top or black-top, not `sideways`",
+                path.display(),
+                line + 1
+            )
+        );
+    }
+
+    #[test]
+    fn every_variant_and_setup_loads_back() {
+        let settings = Settings::new();
+        let players = [Player::Human, Player::Human];
+        let games = variants::ALL
+            .into_iter()
+            .map(|variant| Game::of_variant(&settings, players, variant))
+            .chain(
+                setups::ALL
+                    .iter()
+                    .map(|setup| Game::of_setup(&settings, players, setup)),
+            );
+        for (index, mut game) in games.enumerate() {
+            play_first_moves(&mut game, 2);
+            let loaded = reload(&game, &format!("variant_{}", index));
+            assert_eq!(loaded.board.variant.name, game.board.variant.name);
+            assert_eq!(loaded.board.to_fen(), game.board.to_fen());
+            assert_eq!(loaded.start, game.start);
+            assert_eq!(loaded.moves, game.moves);
+        }
+    }
+}
@@ -0,0 +1,349 @@
+use crate::config::Settings;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::fs;
+use std::path::Path;
+
+//everything a key can do during a game
+#[derive(Clone, Copy, PartialEq)]
+pub enum Action {
+    Up,
+    Down,
+    Left,
+    Right,
+    Select,
+    Move,
+    Pocket,
+    CycleTheme,
+    Keybinds,
+    Stats,
+    BookMoves,
+    Quit,
+}
+
+impl Action {
+    //in the order they are listed on the keybinds screen
+    pub const ALL: [Action; 12] = [
+        Action::Up,
+        Action::Down,
+        Action::Left,
+        Action::Right,
+        Action::Select,
+        Action::Move,
+        Action::Pocket,
+        Action::CycleTheme,
+        Action::Keybinds,
+        Action::Stats,
+        Action::BookMoves,
+        Action::Quit,
+    ];
+
+    //name used in the keymap file
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::Left => "left",
+            Action::Right => "right",
+            Action::Select => "select",
+            Action::Move => "move",
+            Action::Pocket => "pocket",
+            Action::CycleTheme => "theme",
+            Action::Keybinds => "keybinds",
+            Action::Stats => "stats",
+            Action::BookMoves => "book",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            Action::Up => "Move arrow up",
+            Action::Down => "Move arrow down",
+            Action::Left => "Move arrow left",
+            Action::Right => "Move arrow right",
+            Action::Select => "Select piece to move",
+            Action::Move => "Move piece",
+            Action::Pocket => "Pick a piece to drop (crazyhouse)",
+            Action::CycleTheme => "Cycle colour theme",
+            Action::Keybinds => "Show keybinds",
+            Action::Stats => "Show search stats",
+            Action::BookMoves => "Show book moves",
+            Action::Quit => "Quit game",
+        }
+    }
+}
+
+pub struct Keymap {
+    bindings: Vec<((KeyCode, KeyModifiers), Action)>,
+}
+
+impl Keymap {
+    //arrow keys, wasd and vi style hjkl all move the arrow out of the box
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: Vec::new(),
+        };
+        keymap.bind(
+            Action::Up,
+            &[KeyCode::Up, KeyCode::Char('w'), KeyCode::Char('k')],
+        );
+        keymap.bind(
+            Action::Down,
+            &[KeyCode::Down, KeyCode::Char('s'), KeyCode::Char('j')],
+        );
+        keymap.bind(
+            Action::Left,
+            &[KeyCode::Left, KeyCode::Char('a'), KeyCode::Char('h')],
+        );
+        keymap.bind(
+            Action::Right,
+            &[KeyCode::Right, KeyCode::Char('d'), KeyCode::Char('l')],
+        );
+        keymap.bind(Action::Select, &[KeyCode::Char(' ')]);
+        keymap.bind(Action::Move, &[KeyCode::Enter]);
+        keymap.bind(Action::Pocket, &[KeyCode::Char('p')]);
+        keymap.bind(Action::CycleTheme, &[KeyCode::Char('t')]);
+        keymap.bind(Action::Keybinds, &[KeyCode::Char('?')]);
+        keymap.bind(Action::Stats, &[KeyCode::F(3)]);
+        keymap.bind(Action::BookMoves, &[KeyCode::Char('b')]);
+        keymap.bind(Action::Quit, &[KeyCode::Esc]);
+        keymap
+    }
+
+    //the default keymap with the keymap file named in the settings applied on top.
+    //a broken file is reported and the defaults are kept
+    pub fn load(settings: &Settings) -> (Keymap, Option<String>) {
+        let mut keymap = Keymap::new();
+        let path = match settings.keymap_path() {
+            Some(path) => path,
+            None => return (keymap, None),
+        };
+        if !path.exists() {
+            //only the default file is optional, one named in the settings should be there
+            if settings.keymap == Settings::new().keymap {
+                return (keymap, None);
+            }
+            return (keymap, Some(format!("{}: file not found", path.display())));
+        }
+        let applied = fs::read_to_string(&path)
+            .map_err(|error| format!("{}: {}", path.display(), error))
+            .and_then(|text| keymap.apply(&text, &path));
+        match applied {
+            Ok(()) => (keymap, None),
+            Err(error) => (Keymap::new(), Some(error)),
+        }
+    }
+
+    //each line is `action = key, key, ...` and replaces every default key of that
+    //action. `path` is only for the errors
+    fn apply(&mut self, text: &str, path: &Path) -> Result<(), String> {
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("{}:{}: {}", path.display(), number + 1, message);
+            let (name, keys) = line
+                .split_once('=')
+                .ok_or_else(|| error(String::from("expected `action = key, key`")))?;
+            let name = name.trim();
+            let action = Action::ALL
+                .into_iter()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| error(format!("unknown action `{}`", name)))?;
+            let mut bound = Vec::new();
+            for key in keys.split(',') {
+                let key = key.trim();
+                bound.push(parse_key(key).ok_or_else(|| error(format!("unknown key `{}`", key)))?);
+            }
+            self.bindings.retain(|(_, existing)| *existing != action);
+            self.bind_keys(action, &bound);
+        }
+        Ok(())
+    }
+
+    //the default keys, none of which need ctrl or alt
+    fn bind(&mut self, action: Action, codes: &[KeyCode]) {
+        let keys: Vec<(KeyCode, KeyModifiers)> = codes
+            .iter()
+            .map(|code| (*code, KeyModifiers::NONE))
+            .collect();
+        self.bind_keys(action, &keys);
+    }
+
+    fn bind_keys(&mut self, action: Action, keys: &[(KeyCode, KeyModifiers)]) {
+        for key in keys {
+            //a key can only do one thing, the newest binding wins
+            self.bindings.retain(|(bound, _)| bound != key);
+            self.bindings.push((*key, action));
+        }
+    }
+
+    //ctrl and alt have to match the binding, so ctrl+w isn't w. shift only picks
+    //the letter: shifted letters fall back to their lowercase binding unless they
+    //are bound themselves. ctrl+c always quits, whatever the keymap says
+    pub fn action(&self, event: KeyEvent) -> Option<Action> {
+        let modifiers = event.modifiers.difference(KeyModifiers::SHIFT);
+        if event.code == KeyCode::Char('c') && modifiers == KeyModifiers::CONTROL {
+            return Some(Action::Quit);
+        }
+        let find = |code: KeyCode| {
+            self.bindings
+                .iter()
+                .find(|(bound, _)| *bound == (code, modifiers))
+                .map(|(_, action)| *action)
+        };
+        match event.code {
+            KeyCode::Char(letter) if letter.is_ascii_uppercase() => {
+                find(event.code).or_else(|| find(KeyCode::Char(letter.to_ascii_lowercase())))
+            }
+            _ => find(event.code),
+        }
+    }
+
+    //the first key bound to an action, for short hints
+    pub fn key_for(&self, action: Action) -> String {
+        match self.bindings.iter().find(|(_, bound)| *bound == action) {
+            Some((key, _)) => key_name(*key),
+            None => String::from("unbound"),
+        }
+    }
+
+    //readable list of the keys bound to an action, e.g. "Up / W / K"
+    pub fn keys_for(&self, action: Action) -> String {
+        let names: Vec<String> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| *bound == action)
+            .map(|(key, _)| key_name(*key))
+            .collect();
+        if names.is_empty() {
+            String::from("unbound")
+        } else {
+            names.join(" / ")
+        }
+    }
+}
+
+const NAMED_KEYS: [(&str, KeyCode); 12] = [
+    ("Space", KeyCode::Char(' ')),
+    ("Enter", KeyCode::Enter),
+    ("Esc", KeyCode::Esc),
+    ("Tab", KeyCode::Tab),
+    ("Backspace", KeyCode::Backspace),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("Delete", KeyCode::Delete),
+];
+
+//written in front of a key, like Ctrl+W
+const MODIFIERS: [(&str, KeyModifiers); 2] = [
+    ("Ctrl+", KeyModifiers::CONTROL),
+    ("Alt+", KeyModifiers::ALT),
+];
+
+fn parse_key(name: &str) -> Option<(KeyCode, KeyModifiers)> {
+    let mut name = name;
+    let mut modifiers = KeyModifiers::NONE;
+    while let Some((prefix, modifier)) = MODIFIERS.iter().find(|(prefix, _)| {
+        name.len() > prefix.len()
+            && name
+                .get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+    }) {
+        name = &name[prefix.len()..];
+        modifiers |= *modifier;
+    }
+    match parse_code(name)? {
+        //terminals send ctrl and alt letters in lowercase
+        KeyCode::Char(letter) if !modifiers.is_empty() => {
+            Some((KeyCode::Char(letter.to_ascii_lowercase()), modifiers))
+        }
+        code => Some((code, modifiers)),
+    }
+}
+
+fn parse_code(name: &str) -> Option<KeyCode> {
+    if let Some((_, code)) = NAMED_KEYS
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(name))
+    {
+        return Some(*code);
+    }
+    if let Some(number) = name.strip_prefix(['F', 'f']).and_then(|n| n.parse().ok()) {
+        return Some(KeyCode::F(number));
+    }
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        (Some(letter), None) => Some(KeyCode::Char(letter)),
+        _ => None,
+    }
+}
+
+fn key_name((code, modifiers): (KeyCode, KeyModifiers)) -> String {
+    let mut name: String = MODIFIERS
+        .iter()
+        .filter(|(_, modifier)| modifiers.contains(*modifier))
+        .map(|(prefix, _)| *prefix)
+        .collect();
+    if let Some((known, _)) = NAMED_KEYS.iter().find(|(_, known)| *known == code) {
+        name.push_str(known);
+        return name;
+    }
+    match code {
+        KeyCode::F(number) => name.push_str(&format!("F{}", number)),
+        KeyCode::Char(letter) => name.extend(letter.to_uppercase()),
+        _ => name.push('?'),
+    }
+    name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_file_replaces_default_keys() {
+        let text = "# vi keys only\n\nup = k\nselect = #\nmove = Enter, Space\n";
+        let mut keymap = Keymap::new();
+        keymap.apply(text, Path::new("keymap.conf")).unwrap();
+        let press = |code: KeyCode| keymap.action(KeyEvent::from(code));
+        assert!(press(KeyCode::Char('k')) == Some(Action::Up));
+        assert!(press(KeyCode::Char('w')).is_none());
+        assert!(press(KeyCode::Char('#')) == Some(Action::Select));
+        assert!(press(KeyCode::Char(' ')) == Some(Action::Move));
+
+        let error = |text: &str| {
+            Keymap::new()
+                .apply(text, Path::new("keymap.conf"))
+                .unwrap_err()
+        };
+        assert_eq!(
+            error("up = k\nfly = x"),
+            "keymap.conf:2: unknown action `fly`"
+        );
+        assert_eq!(error("up = PageUp"), "keymap.conf:1: unknown key `PageUp`");
+        assert_eq!(error("up k"), "keymap.conf:1: expected `action = key, key`");
+    }
+
+    #[test]
+    fn ctrl_and_alt_have_to_match() {
+        let mut keymap = Keymap::new();
+        let text = "quit = Ctrl+Q\ntheme = alt+T";
+        keymap.apply(text, Path::new("keymap.conf")).unwrap();
+        let press =
+            |code: KeyCode, modifiers: KeyModifiers| keymap.action(KeyEvent::new(code, modifiers));
+        assert!(press(KeyCode::Char('q'), KeyModifiers::CONTROL) == Some(Action::Quit));
+        assert!(press(KeyCode::Char('q'), KeyModifiers::NONE).is_none());
+        assert!(press(KeyCode::Char('t'), KeyModifiers::ALT) == Some(Action::CycleTheme));
+        assert!(press(KeyCode::Char('w'), KeyModifiers::CONTROL).is_none());
+        assert!(press(KeyCode::Char('W'), KeyModifiers::SHIFT) == Some(Action::Up));
+        //ctrl+c gets out even though quit is ctrl+q now
+        assert!(press(KeyCode::Char('c'), KeyModifiers::CONTROL) == Some(Action::Quit));
+        assert_eq!(keymap.keys_for(Action::Quit), "Ctrl+Q");
+    }
+}
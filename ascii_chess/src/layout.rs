@@ -0,0 +1,149 @@
+use crate::theme::CELL_WIDTH;
+
+//the biggest a square gets, 3x is CELL_WIDTH * 3 columns by 3 rows
+const MAX_SCALE: u16 = 3;
+//narrowest side panel worth drawing next to the board
+const PANEL_WIDTH: u16 = 28;
+//rows kept free under the board for the panel when it can't go beside it
+const PANEL_HEIGHT: u16 = 6;
+
+//where the board is drawn and how big its squares are
+#[derive(Clone, Copy)]
+pub struct BoardArea {
+    pub x: u16,
+    pub y: u16,
+    pub scale: u16,
+    pub rows: u16,
+    pub columns: u16,
+}
+
+impl BoardArea {
+    pub fn square_width(&self) -> u16 {
+        CELL_WIDTH * self.scale
+    }
+
+    pub fn square_height(&self) -> u16 {
+        self.scale
+    }
+
+    //the row marker column and the squares
+    pub fn width(&self) -> u16 {
+        1 + self.columns * self.square_width()
+    }
+
+    //the squares, then the file letters and the column marker underneath
+    pub fn height(&self) -> u16 {
+        self.rows * self.square_height() + 2
+    }
+}
+
+#[derive(Clone, Copy)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+pub struct Layout {
+    pub board: BoardArea,
+    pub panel: Option<Rect>,
+    pub prompt: Rect,
+}
+
+//smallest terminal a board of this size can be played in
+pub fn minimum_size(rows: usize, columns: usize) -> (u16, u16) {
+    let board = BoardArea {
+        x: 0,
+        y: 0,
+        scale: 1,
+        rows: rows as u16,
+        columns: columns as u16,
+    };
+    (board.width(), board.height() + 1)
+}
+
+//fits the board, side panel and prompt line into the terminal. the board gets
+//the biggest scale that still leaves room for the prompt, the panel goes to the
+//right of it if there is room, otherwise underneath. None if nothing fits
+pub fn compute(width: u16, height: u16, rows: usize, columns: usize) -> Option<Layout> {
+    let (min_width, min_height) = minimum_size(rows, columns);
+    if width < min_width || height < min_height {
+        return None;
+    }
+
+    let area = |scale: u16| BoardArea {
+        x: 0,
+        y: 0,
+        scale,
+        rows: rows as u16,
+        columns: columns as u16,
+    };
+    let fits = |scale: u16, extra_width: u16, extra_height: u16| {
+        let board = area(scale);
+        board.width() + extra_width <= width && board.height() + 1 + extra_height <= height
+    };
+    //prefer a bigger board with the panel beside it, then below it, then no panel at all
+    let mut choice = None;
+    for scale in (1..=MAX_SCALE).rev() {
+        if fits(scale, PANEL_WIDTH + 2, 0) {
+            choice = Some((scale, PanelSide::Right));
+        } else if fits(scale, 0, PANEL_HEIGHT) {
+            choice = Some((scale, PanelSide::Below));
+        }
+        if choice.is_some() {
+            break;
+        }
+    }
+    let (scale, side) = choice.unwrap_or((1, PanelSide::Hidden));
+
+    let mut board = area(scale);
+    let group_width = match side {
+        PanelSide::Right => board.width() + 2 + PANEL_WIDTH,
+        _ => board.width(),
+    };
+    board.x = (width - group_width) / 2;
+    //a blank line above the board when it can be spared
+    let panel_rows = if side == PanelSide::Below {
+        PANEL_HEIGHT
+    } else {
+        0
+    };
+    if board.height() + 2 + panel_rows <= height {
+        board.y = 1;
+    }
+
+    let prompt = Rect {
+        x: board.x,
+        y: height - 1,
+        width: width - board.x,
+        height: 1,
+    };
+    let panel = match side {
+        PanelSide::Right => Some(Rect {
+            x: board.x + board.width() + 2,
+            y: board.y,
+            width: PANEL_WIDTH.max(width - board.x - board.width() - 2),
+            height: height - 1 - board.y,
+        }),
+        PanelSide::Below => Some(Rect {
+            x: board.x,
+            y: board.y + board.height() + 1,
+            width: width - board.x,
+            height: height - 1 - (board.y + board.height() + 1),
+        }),
+        PanelSide::Hidden => None,
+    };
+    Some(Layout {
+        board,
+        panel,
+        prompt,
+    })
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum PanelSide {
+    Right,
+    Below,
+    Hidden,
+}
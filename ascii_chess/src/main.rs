@@ -1,112 +1,664 @@
+mod bitboard;
 mod board;
-use board::Matrix;
-use colored::*;
-use crossterm::event::{read, Event, KeyCode, KeyEventKind};
-use std::io;
+mod book;
+mod clock;
+mod config;
+mod game;
+mod keymap;
+mod layout;
+mod menu;
+mod perft;
+mod pgn;
+mod pieces;
+mod search;
+mod setups;
+mod terminal;
+mod theme;
+mod variants;
+mod zobrist;
+use bitboard::Position;
+use board::{Colour, Matrix, MoveError};
+use book::Book;
+use config::Settings;
+use crossterm::event::{poll, read, Event, KeyEventKind, MouseButton, MouseEventKind};
+use crossterm::style::Color;
+use game::{Game, Player};
+use keymap::{Action, Keymap};
+use layout::Layout;
+use menu::{Choice, Leave};
+use search::{Report, Search};
+use std::env;
+use std::fs;
+use std::path::Path;
 use std::process;
+use std::time::Duration;
+use terminal::{Frame, Screen, Style};
+use theme::Theme;
+use variants::Rules;
 
 fn main() {
-    welcome();
+    let args: Vec<String> = env::args().collect();
+    //`perft <depth> [fen]` counts moves for testing the move generator, no game is started
+    if args.get(1).map(String::as_str) == Some("perft") {
+        if let Err(error) = perft::run(&args[2..]) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+    //`bench [depth]` compares perft speed of the array board and the bitboards
+    if args.get(1).map(String::as_str) == Some("bench") {
+        if let Err(error) = perft::bench(&args[2..]) {
+            eprintln!("{}", error);
+            process::exit(1);
+        }
+        return;
+    }
+    let (mut settings, mut errors) = Settings::load();
+    let (mut theme, theme_errors) = theme::select(&args, &settings);
+    errors.extend(theme_errors);
+    let (mut keymap, keymap_error) = Keymap::load(&settings);
+    errors.extend(keymap_error);
+    let (book, book_error) = Book::load(&settings);
+    errors.extend(book_error);
+
+    //restores the terminal when it goes out of scope, even if the game panics
+    let mut screen = match Screen::open() {
+        Ok(screen) => screen,
+        Err(error) => {
+            eprintln!("Could not set up the terminal: {}", error);
+            process::exit(1);
+        }
+    };
+    //--load <file> skips the menu and goes straight into the saved game
+    let mut next = match args.iter().position(|arg| arg == "--load") {
+        Some(index) => match args.get(index + 1) {
+            Some(path) => match Game::load(Path::new(path)) {
+                Ok(game) => Some(game),
+                Err(error) => {
+                    errors.push(error);
+                    None
+                }
+            },
+            None => {
+                errors.push(String::from("--load needs a saved game file"));
+                None
+            }
+        },
+        None => None,
+    };
+    //--chess960 [number] starts a human vs human chess960 game, at random without a number
+    if let Some(index) = args.iter().position(|arg| arg == "--chess960") {
+        let number = match args.get(index + 1).filter(|arg| !arg.starts_with("--")) {
+            Some(text) => game::parse_chess960(text),
+            None => Some((book::random() % 960) as u32),
+        };
+        match number {
+            Some(number) => {
+                let players = [Player::Human, Player::Human];
+                next = Some(Game::new(&settings, players, Some(number)));
+            }
+            None => errors.push(String::from(
+                "--chess960 takes a start position from 0 to 959",
+            )),
+        }
+    }
+    //--variant <name> starts a human vs human game of a variant from its start position
+    if let Some(index) = args.iter().position(|arg| arg == "--variant") {
+        match args.get(index + 1).map(|name| variants::by_name(name)) {
+            Some(Some(variant)) => {
+                let players = [Player::Human, Player::Human];
+                next = Some(Game::of_variant(&settings, players, variant));
+            }
+            _ => errors.push(format!(
+                "--variant takes one of {}",
+                variants::ALL.map(|variant| variant.name).join(", ")
+            )),
+        }
+    }
+    //--fen <fen> starts a human vs human game from any position, fairy pieces too.
+    //the variant is worked out from the size of the board
+    if let Some(index) = args.iter().position(|arg| arg == "--fen") {
+        match args.get(index + 1).map(|fen| Matrix::from_fen(fen)) {
+            Some(Ok(board)) => {
+                let players = [Player::Human, Player::Human];
+                next = Some(Game::from_board(&settings, players, board));
+            }
+            Some(Err(error)) => errors.push(format!("--fen: {}", error)),
+            None => errors.push(String::from("--fen needs a position")),
+        }
+    }
+    //config errors are shown until the first game starts
+    loop {
+        let game = match next.take() {
+            Some(game) => game,
+            None => match menu::main_menu(
+                &mut screen,
+                &mut theme,
+                &mut keymap,
+                &mut settings,
+                &mut errors,
+            ) {
+                Choice::Play(game) => *game,
+                Choice::Quit => break,
+            },
+        };
+        errors = play(
+            &mut screen,
+            &mut theme,
+            &keymap,
+            &settings,
+            book.as_ref(),
+            game,
+        )
+        .into_iter()
+        .collect();
+    }
+}
 
+//runs one game until the player quits back to the menu. an unfinished game is
+//autosaved on the way out, the error is returned if that didn't work
+fn play(
+    screen: &mut Screen,
+    theme: &mut Theme,
+    keymap: &Keymap,
+    settings: &Settings,
+    book: Option<&Book>,
+    mut game: Game,
+) -> Option<String> {
     let mut select_mode: bool = false;
-    let mut chess_board = Matrix::new(); //generates board with no pieces
-    chess_board.populate_pieces(); //populates board with pieces
-    chess_board.display(); //displays to screen
+    let mut show_help: bool = false;
+    //set once the game is over, saying how it ended
+    let mut result: Option<String> = game_over(&game);
+    //made the first time the computer has to think, its table is big
+    let mut search: Option<Search> = None;
+    //what the computer's last search did, shown with the stats key
+    let mut last_report: Option<Report> = None;
+    let mut book_move: bool = false;
+    let mut show_stats: bool = false;
+    let mut show_book: bool = false;
+    //why the last move didn't go through, until another piece is picked up
+    let mut rejected: Option<MoveError> = None;
+    //two people at one keyboard playing fog of war hand it over between moves
+    let hot_seat_fog = game.board.variant.rules == Rules::FogOfWar
+        && game.players == [Player::Human, Player::Human];
+    if hot_seat_fog && result.is_none() {
+        menu::pass_turn(screen, keymap, game.board.turn);
+    }
 
     loop {
-        let input = read();
-        match input {
-            //github wiork
+        let mut frame = screen.frame();
+        //worked out every frame so resizing the terminal just works
+        let layout = layout::compute(
+            frame.width,
+            frame.height,
+            game.board.rows(),
+            game.board.columns(),
+        );
+        match &layout {
+            Some(layout) => {
+                let viewer = fog_viewer(&game, result.as_deref());
+                game.board.display(theme, &mut frame, &layout.board, viewer); //displays to screen
+                draw_panel(
+                    &mut frame,
+                    layout,
+                    &game,
+                    theme,
+                    keymap,
+                    show_help,
+                    &notes(
+                        &game,
+                        book,
+                        show_stats,
+                        last_report.as_ref(),
+                        book_move,
+                        show_book,
+                    ),
+                );
+                draw_prompt(
+                    &mut frame,
+                    layout,
+                    &game,
+                    keymap,
+                    select_mode,
+                    result.as_deref(),
+                    rejected,
+                );
+            }
+            None => draw_too_small(&mut frame, &game.board),
+        }
+        if screen.draw(frame).is_err() {
+            break;
+        }
+
+        //with a clock running the screen is redrawn a few times a second even
+        //when nothing is pressed, so the time keeps counting down
+        if let Some(running) = &game.clock {
+            if result.is_none() {
+                if let Some(loser) = running.flagged() {
+                    result = Some(format!("{} ran out of time", loser.name()));
+                    if settings.bell {
+                        screen.bell();
+                    }
+                }
+            }
+            if result.is_none()
+                && game.to_move() == Player::Human
+                && !matches!(poll(Duration::from_millis(100)), Ok(true))
+            {
+                continue;
+            }
+        }
+
+        //the computer moves straight after the board is drawn with its prompt up,
+        //keys pressed while it thinks are read once it has moved
+        if let (None, Player::Computer(level)) = (&result, game.to_move()) {
+            //straight from the book while the game is still in it, so openings vary
+            let fits = game.board.fits_bitboards();
+            let mut chosen = book
+                .filter(|_| fits)
+                .and_then(|book| book.pick(&game.board));
+            book_move = chosen.is_some();
+            if !fits {
+                let report = search
+                    .get_or_insert_with(Search::new)
+                    .think_on_board(&game.board, level);
+                chosen = report.best;
+                last_report = Some(report);
+            } else if chosen.is_none() {
+                let previous = &game.positions[..game.positions.len() - 1];
+                let report = search.get_or_insert_with(Search::new).think(
+                    &Position::from(&game.board),
+                    previous,
+                    level,
+                );
+                chosen = report.best;
+                last_report = Some(report);
+            }
+            if let Some(chosen) = chosen {
+                game.board.make_move(chosen);
+                game.record(chosen);
+                result = game_over(&game);
+            }
+            continue;
+        }
+
+        let mut played = None;
+        match read() {
             Ok(Event::Key(key_event)) => {
                 if key_event.kind == KeyEventKind::Release {
-                    match key_event.code {
-                        _ => {
-                            continue;
-                        }
-                    }
+                    continue;
                 }
-                match key_event.code {
-                    KeyCode::Char('a') => {
-                        chess_board.mutate_arrow((0, -1));
-                        update_terminal(&mut chess_board);
+                let action = match keymap.action(key_event) {
+                    Some(action) => action,
+                    None => continue,
+                };
+                //the board is left as it is for the players to look at once the game is over
+                let playing = result.is_none();
+                match action {
+                    Action::Left => game.board.mutate_arrow((0, -1)),
+                    Action::Right => game.board.mutate_arrow((0, 1)),
+                    Action::Up => game.board.mutate_arrow((-1, 0)),
+                    Action::Down => game.board.mutate_arrow((1, 0)),
+                    Action::Select if playing => {
+                        game.board.select_piece();
+                        game.board.dropping = None;
+                        select_mode = true;
+                        rejected = None;
+                        //selection mode, select piece and append to an appropriate coordinate to move the piece
                     }
-                    KeyCode::Char('d') => {
-                        chess_board.mutate_arrow((0, 1));
-                        update_terminal(&mut chess_board);
+                    Action::Move if select_mode && playing => {
+                        played = Some(game.board.movement(game.board.selected_piece));
+                        select_mode = false;
                     }
-                    KeyCode::Char('w') => {
-                        chess_board.mutate_arrow((-1, 0));
-                        update_terminal(&mut chess_board);
+                    Action::Move if playing => {
+                        if let Some(piece) = game.board.dropping.take() {
+                            played = Some(game.board.drop_piece(piece));
+                        }
                     }
-                    KeyCode::Char('s') => {
-                        chess_board.mutate_arrow((1, 0));
-                        update_terminal(&mut chess_board);
+                    //each press picks the next piece in the pocket, then none again
+                    Action::Pocket if playing => {
+                        game.board.dropping = game.board.next_in_pocket(game.board.dropping);
+                        select_mode = false;
+                        rejected = None;
                     }
-                    KeyCode::Char(' ') => {
-                        chess_board.select_piece();
-                        select_mode = true;
-                        //selection mode, select piece and append to an appropriate coordinate to move the piece
+                    Action::Select | Action::Move | Action::Pocket => {}
+                    Action::CycleTheme => theme.next_palette(),
+                    Action::Keybinds => show_help = !show_help,
+                    Action::Stats => show_stats = !show_stats,
+                    Action::BookMoves => show_book = !show_book,
+                    Action::Quit if result.is_some() => break,
+                    Action::Quit => {
+                        if confirm_leave(screen, keymap, &game) {
+                            break;
+                        }
                     }
-                    KeyCode::Enter => {
-                        //PROBLEM: move arrows to place you want to move,
-                        if select_mode {
-                            select_mode = chess_board.movement(chess_board.selected_piece);
-                            update_terminal(&mut chess_board);
+                }
+            }
+            //click a piece then click where it should go, or drag it there
+            Ok(Event::Mouse(mouse_event)) => {
+                let area = match &layout {
+                    Some(layout) if result.is_none() => layout.board,
+                    _ => continue,
+                };
+                let square = match game
+                    .board
+                    .square_at(mouse_event.column, mouse_event.row, &area)
+                {
+                    Some(square) => square,
+                    None => continue,
+                };
+                match mouse_event.kind {
+                    MouseEventKind::Down(MouseButton::Left) => {
+                        game.board.arrow = square;
+                        if let Some(piece) = game.board.dropping.take() {
+                            played = Some(game.board.drop_piece(piece));
+                        } else if select_mode && square != game.board.selected_piece {
+                            played = Some(game.board.movement(game.board.selected_piece));
+                            select_mode = false;
+                        } else {
+                            game.board.select_piece();
+                            select_mode = true;
+                            rejected = None;
                         }
                     }
-                    KeyCode::Char('k') => {
-                        update_terminal(&mut chess_board);
-                        show_keybinds();
+                    MouseEventKind::Up(MouseButton::Left)
+                        if select_mode && square != game.board.selected_piece =>
+                    {
+                        game.board.arrow = square;
+                        played = Some(game.board.movement(game.board.selected_piece));
+                        select_mode = false;
                     }
-
-                    KeyCode::Esc => break,
                     _ => {}
                 }
             }
-            _ => {}
+            Ok(Event::Resize(_, _)) => screen.invalidate(),
+            Ok(_) => {}
+            Err(_) => break,
+        }
+
+        match played {
+            Some(Ok(played)) => {
+                game.record(played);
+                result = game_over(&game);
+                if hot_seat_fog && result.is_none() {
+                    menu::pass_turn(screen, keymap, game.board.turn);
+                }
+            }
+            Some(Err(error)) => {
+                rejected = Some(error);
+                if settings.bell {
+                    screen.bell();
+                }
+            }
+            None => {}
+        }
+    }
+
+    //a game that is over has nothing left to resume, and one nobody moved in
+    //shouldn't replace the last game
+    let path = game::autosave_path()?;
+    if result.is_some() {
+        let _ = fs::remove_file(&path);
+        return None;
+    }
+    if game.moves.is_empty() {
+        return None;
+    }
+    game.save(&path).err()
+}
+
+//whose side of the fog of war the board is drawn from: the human's, or the
+//side to move when two people share the screen. everything shows once it's over
+fn fog_viewer(game: &Game, result: Option<&str>) -> Option<Colour> {
+    if game.board.variant.rules != Rules::FogOfWar || result.is_some() {
+        return None;
+    }
+    match game.players {
+        [Player::Human, Player::Human] => Some(game.board.turn),
+        [Player::Human, _] => Some(Colour::White),
+        [_, Player::Human] => Some(Colour::Black),
+        _ => None,
+    }
+}
+
+//checkmate or stalemate once the side to move has no legal moves left, or a
+//draw once the same position has come up three times
+fn game_over(game: &Game) -> Option<String> {
+    let chess_board = &game.board;
+    if let Some(winner) = chess_board.variant_win() {
+        return Some(format!(
+            "{}, {} wins",
+            chess_board.variant.rules.win_reason(),
+            winner.name()
+        ));
+    }
+    if game.repetitions() >= 3 {
+        return Some(String::from("Draw by threefold repetition"));
+    }
+    if !chess_board.legal_moves().is_empty() {
+        return None;
+    }
+    if chess_board.in_check(chess_board.turn) {
+        return Some(format!(
+            "Checkmate, {} wins",
+            chess_board.turn.opponent().name()
+        ));
+    }
+    Some(String::from("Stalemate"))
+}
+
+//asks before leaving an unfinished game and saves it if the player wants.
+//returns true if they are leaving
+fn confirm_leave(screen: &mut Screen, keymap: &Keymap, game: &Game) -> bool {
+    let mut errors = Vec::new();
+    loop {
+        match menu::leave_game(screen, keymap, &errors) {
+            Leave::KeepPlaying => return false,
+            Leave::Quit => return true,
+            //a PGN copy goes next to the save, for other chess programs
+            Leave::Save => match game::new_save_path() {
+                Some(path) => match game
+                    .save(&path)
+                    .and_then(|_| pgn::write(game, &path.with_extension("pgn")))
+                {
+                    Ok(()) => return true,
+                    Err(error) => errors = vec![error],
+                },
+                None => errors = vec![String::from("no config folder to save the game in")],
+            },
         }
     }
 }
 
-fn show_keybinds() {
-    println!("  Useful Keybinds: ");
-    println!("  Move keys: {}", "AWSD".red());
-    println!("  Move piece: {}", "Enter".red());
-    println!("  Select piece to move: {}", "Space".red());
-    println!("  Quit game: {}", "Esc".red());
-    println!("  Show keybinds: {}", "K".red());
+//side panel with the game state, and the keybinds when they are toggled on
+fn draw_panel(
+    frame: &mut Frame,
+    layout: &Layout,
+    game: &Game,
+    theme: &Theme,
+    keymap: &Keymap,
+    show_help: bool,
+    notes: &[String],
+) {
+    let panel = match layout.panel {
+        Some(panel) => panel,
+        None => return,
+    };
+    let mut turn = format!("Turn: {}", game.board.turn.name());
+    match game.chess960 {
+        Some(number) => turn.push_str(&format!("  Chess960 #{}", number)),
+        None if game.board.chess960 => turn.push_str("  Chess960"),
+        None => {}
+    }
+    if game.board.variant.name != variants::STANDARD.name {
+        turn.push_str(&format!("  {}", game.board.variant.title));
+    }
+    frame.print(panel.x, panel.y, &turn, Style::default());
+    let palette = format!("Theme: {}", theme.palette_name());
+    frame.print(panel.x, panel.y + 1, &palette, Style::default());
+    let mut row = panel.y + 3;
+    if let Some(clock) = &game.clock {
+        let times = format!(
+            "White {}  Black {}",
+            clock::format_time(clock.remaining(Colour::White)),
+            clock::format_time(clock.remaining(Colour::Black))
+        );
+        frame.print(panel.x, panel.y + 2, &times, Style::default());
+        row += 1;
+    }
+    if game.board.variant.rules == Rules::ThreeCheck {
+        let checks = format!(
+            "Checks given: White {}  Black {}",
+            game.board.checks[0], game.board.checks[1]
+        );
+        frame.print(panel.x, row, &checks, Style::default());
+        row += 2;
+    }
+    if game.board.variant.rules == Rules::Crazyhouse {
+        for (side, colour) in [Colour::White, Colour::Black].into_iter().enumerate() {
+            let pocket: String = board::POCKET
+                .iter()
+                .zip(game.board.pockets[side])
+                .flat_map(|(piece, count)| {
+                    std::iter::repeat_n(piece.letter().to_ascii_uppercase(), count as usize)
+                })
+                .collect();
+            let line = format!(
+                "{} pocket: {}",
+                colour.name(),
+                if pocket.is_empty() { "-" } else { &pocket }
+            );
+            frame.print(panel.x, row, &line, Style::default());
+            row += 1;
+        }
+        let hint = format!("Drop a piece with {}", keymap.keys_for(Action::Pocket));
+        frame.print(panel.x, row, &hint, Style::fg(Color::DarkGrey));
+        row += 2;
+    }
+    for line in notes {
+        frame.print(panel.x, row, line, Style::fg(Color::DarkGrey));
+        row += 1;
+    }
+    if !notes.is_empty() {
+        row += 1;
+    }
+    if show_help {
+        menu::draw_keybinds(frame, keymap, panel.x, row, panel.y + panel.height);
+    } else {
+        let hint = format!("Show keybinds: {}", keymap.keys_for(Action::Keybinds));
+        frame.print(panel.x, row, &hint, Style::default());
+    }
+}
+
+//the search stats and book moves the player asked to see, for the side panel
+fn notes(
+    game: &Game,
+    book: Option<&Book>,
+    show_stats: bool,
+    last_report: Option<&Report>,
+    book_move: bool,
+    show_book: bool,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    if show_stats && book_move {
+        lines.push(String::from("Last move from the book"));
+    } else if let Some(report) = last_report.filter(|_| show_stats) {
+        lines.push(format!("Depth {}  score {}", report.depth, report.score));
+        lines.push(format!(
+            "Nodes {}  ({}/s)",
+            report.nodes,
+            report.nodes_per_second()
+        ));
+        lines.push(format!("Table hits {}", report.table_hits));
+    }
+    if show_book {
+        let moves = book.map(|book| book.moves(&game.board)).unwrap_or_default();
+        let total: u32 = moves.iter().map(|(_, weight)| *weight as u32).sum();
+        lines.push(match (book, moves.is_empty()) {
+            (None, _) => String::from("Book moves: no book"),
+            (Some(_), true) => String::from("Book moves: out of book"),
+            (Some(_), false) => String::from("Book moves:"),
+        });
+        for (chosen, weight) in moves.iter().take(5) {
+            lines.push(format!(
+                "  {}  {}%",
+                chosen.name(),
+                *weight as u32 * 100 / total.max(1)
+            ));
+        }
+    }
+    lines
 }
 
-fn update_terminal(ref_board: &mut Matrix) {
-    print!("\x1B[2J"); //clears terminal
-    print!("\x1B[H"); //move cursor top left
-    ref_board.display();
-    //println!("Piece Selected: {:?}",ref_board.arrow);
-    //println!("");
+//one line at the bottom telling the player what to do next
+fn draw_prompt(
+    frame: &mut Frame,
+    layout: &Layout,
+    game: &Game,
+    keymap: &Keymap,
+    select_mode: bool,
+    result: Option<&str>,
+    rejected: Option<MoveError>,
+) {
+    let chess_board = &game.board;
+    let mut style = Style::fg(Color::Blue);
+    let text = if let Some(result) = result {
+        format!(
+            "{}, press {} to leave",
+            result,
+            keymap.keys_for(Action::Quit)
+        )
+    } else if game.to_move() != Player::Human {
+        format!("The computer is thinking as {}...", chess_board.turn.name())
+    } else if let Some(piece) = chess_board.dropping {
+        format!(
+            "Dropping a {} from the pocket, pick an empty square and press {}",
+            piece,
+            keymap.keys_for(Action::Move)
+        )
+    } else if let Some(error) = rejected.filter(|_| !select_mode) {
+        style = Style::fg(Color::Red);
+        format!(
+            "Can't move there, {}. Select a piece with {}",
+            error,
+            keymap.keys_for(Action::Select)
+        )
+    } else if select_mode {
+        format!(
+            "Moving the piece on {}, pick a square and press {}",
+            board::square_name(chess_board.selected_piece),
+            keymap.keys_for(Action::Move)
+        )
+    } else {
+        format!(
+            "{} to move{}, select a piece with {}",
+            chess_board.turn.name(),
+            if chess_board.in_check(chess_board.turn) {
+                " and in check"
+            } else {
+                ""
+            },
+            keymap.keys_for(Action::Select)
+        )
+    };
+    let prompt = layout.prompt;
+    let text: String = text.chars().take(prompt.width as usize).collect();
+    frame.print(prompt.x, prompt.y, &text, style);
 }
 
-fn welcome() {
-    print!("\x1B[2J"); //clears terminal
-    print!("\x1B[H"); //move cursor top left
-
-    let mut input = String::new();
-    println!(
-        "{}{}{}{}{}",
-        "Welcome to Ascii Chess on the Terminal. Press ".blue(),
-        "<Enter>".red(),
-        " key to begin, Type ".blue(),
-        "'Esc'".red(),
-        " to Leave".blue()
-    );
-    show_keybinds();
-    io::stdin()
-        .read_line(&mut input)
-        .expect("Failed to read line");
-
-    if input.trim() == "Esc" {
-        process::exit(0);
-    }
-
-    print!("\x1B[2J"); //clears terminal
-    print!("\x1B[H"); //move cursor top left
+fn draw_too_small(frame: &mut Frame, board: &Matrix) {
+    let (width, height) = layout::minimum_size(board.rows(), board.columns());
+    //short lines so the message still fits when the terminal is tiny
+    let lines = [
+        String::from("Terminal"),
+        String::from("too small"),
+        format!("need {}x{}", width, height),
+        format!("have {}x{}", frame.width, frame.height),
+    ];
+    for (row, line) in lines.iter().enumerate() {
+        let x = frame.width.saturating_sub(line.len() as u16) / 2;
+        let y = (frame.height / 2 + row as u16).saturating_sub(2);
+        frame.print(x, y, line, Style::fg(Color::Red));
+    }
 }
@@ -0,0 +1,530 @@
+use crate::board;
+use crate::book;
+use crate::clock::TimeControl;
+use crate::config::{Settings, MAX_AI_LEVEL};
+use crate::game::{self, Game, Player};
+use crate::keymap::{Action, Keymap};
+use crate::setups::{self, Setup};
+use crate::terminal::{Frame, Screen, Style};
+use crate::theme::Theme;
+use crate::variants::{self, Variant};
+use crossterm::event::{read, Event, KeyEventKind, MouseButton, MouseEventKind};
+use crossterm::style::Color;
+
+//what the player picked on the main menu
+pub enum Choice {
+    Play(Box<Game>),
+    Quit,
+}
+
+//what the player picked when leaving a game
+pub enum Leave {
+    KeepPlaying,
+    Save,
+    Quit,
+}
+
+struct Item {
+    label: String,
+    enabled: bool,
+}
+
+impl Item {
+    fn new(label: &str) -> Item {
+        Item {
+            label: String::from(label),
+            enabled: true,
+        }
+    }
+}
+
+//where the first menu item is drawn, under the title and any errors
+const ITEMS_TOP: u16 = 3;
+
+//the menu shown before every game. errors are problems with the config files
+//or the last game, shown until the player starts a game
+pub fn main_menu(
+    screen: &mut Screen,
+    theme: &mut Theme,
+    keymap: &mut Keymap,
+    settings: &mut Settings,
+    errors: &mut Vec<String>,
+) -> Choice {
+    let mut selected = 0;
+    loop {
+        let autosave = game::autosave_path().filter(|path| path.exists());
+        let resume = if autosave.is_some() {
+            Item::new("Resume last game")
+        } else {
+            Item {
+                label: String::from("Resume last game (nothing to resume)"),
+                enabled: false,
+            }
+        };
+        let items = [
+            Item::new("New game: human vs human"),
+            resume,
+            Item::new("New game: vs computer"),
+            Item::new("New game: Chess960"),
+            Item::new("New game: variants"),
+            Item::new("New game: odds and horde"),
+            Item::new("Load game"),
+            Item::new("Settings"),
+            Item::new("Keybinds"),
+            Item::new("Quit"),
+        ];
+        match choose(
+            screen,
+            keymap,
+            "Ascii Chess on the Terminal",
+            &items,
+            &mut selected,
+            errors,
+        ) {
+            Some(0) => {
+                let players = [Player::Human, Player::Human];
+                return Choice::Play(Box::new(Game::new(settings, players, None)));
+            }
+            Some(1) => match autosave.map(|path| Game::load(&path)) {
+                Some(Ok(game)) => return Choice::Play(Box::new(game)),
+                Some(Err(error)) => *errors = vec![error],
+                None => {}
+            },
+            Some(2) => {
+                if let Some(players) = computer_menu(screen, keymap, settings) {
+                    return Choice::Play(Box::new(Game::new(settings, players, None)));
+                }
+            }
+            Some(3) => {
+                if let Some(game) = chess960_menu(screen, keymap, settings) {
+                    return Choice::Play(Box::new(game));
+                }
+            }
+            Some(4) => {
+                if let Some(game) = variants_menu(screen, keymap, settings) {
+                    return Choice::Play(Box::new(game));
+                }
+            }
+            Some(5) => {
+                if let Some(game) = setups_menu(screen, keymap, settings) {
+                    return Choice::Play(Box::new(game));
+                }
+            }
+            Some(6) => {
+                if let Some(game) = load_menu(screen, keymap) {
+                    match game {
+                        Ok(game) => return Choice::Play(Box::new(game)),
+                        Err(error) => *errors = vec![error],
+                    }
+                }
+            }
+            Some(7) => settings_menu(screen, theme, keymap, settings),
+            Some(8) => keybinds(screen, keymap),
+            Some(9) | None => return Choice::Quit,
+            Some(_) => {}
+        }
+    }
+}
+
+//which side the player takes against the computer, None if they backed out
+fn computer_menu(screen: &mut Screen, keymap: &Keymap, settings: &Settings) -> Option<[Player; 2]> {
+    let computer = Player::Computer(settings.ai_level);
+    let items = [
+        Item::new("Play white"),
+        Item::new("Play black"),
+        Item::new("Back"),
+    ];
+    let title = format!(
+        "New game vs computer (level {}, change it in Settings)",
+        settings.ai_level
+    );
+    let mut selected = 0;
+    match choose(screen, keymap, &title, &items, &mut selected, &[]) {
+        Some(0) => Some([Player::Human, computer]),
+        Some(1) => Some([computer, Player::Human]),
+        _ => None,
+    }
+}
+
+//a random chess960 start position, with a way to roll another one before
+//picking who plays. None if the player backed out
+fn chess960_menu(screen: &mut Screen, keymap: &Keymap, settings: &Settings) -> Option<Game> {
+    let computer = Player::Computer(settings.ai_level);
+    let items = [
+        Item::new("Human vs human"),
+        Item::new("Play white vs computer"),
+        Item::new("Play black vs computer"),
+        Item::new("Another position"),
+        Item::new("Back"),
+    ];
+    let mut number = (book::random() % 960) as u32;
+    let mut selected = 0;
+    loop {
+        let rank: String = board::chess960_rank(number)
+            .iter()
+            .map(|piece| piece.letter().to_ascii_uppercase())
+            .collect();
+        let title = format!("Chess960 position #{}: {}", number, rank);
+        let players = match choose(screen, keymap, &title, &items, &mut selected, &[]) {
+            Some(0) => [Player::Human, Player::Human],
+            Some(1) => [Player::Human, computer],
+            Some(2) => [computer, Player::Human],
+            Some(3) => {
+                number = (book::random() % 960) as u32;
+                continue;
+            }
+            _ => return None,
+        };
+        return Some(Game::new(settings, players, Some(number)));
+    }
+}
+
+//the variants played on other boards, then who plays. None if the player backed out
+fn variants_menu(screen: &mut Screen, keymap: &Keymap, settings: &Settings) -> Option<Game> {
+    let choices: Vec<&'static Variant> = variants::ALL
+        .into_iter()
+        //horde is with the odds games, it is another way to start too
+        .filter(|variant| ![variants::STANDARD.name, variants::HORDE.name].contains(&variant.name))
+        .collect();
+    let mut items: Vec<Item> = choices
+        .iter()
+        .map(|variant| Item::new(variant.title))
+        .collect();
+    items.push(Item::new("Back"));
+    let mut selected = 0;
+    loop {
+        let variant = match choose(
+            screen,
+            keymap,
+            "New game: variants",
+            &items,
+            &mut selected,
+            &[],
+        ) {
+            Some(index) if index < choices.len() => choices[index],
+            _ => return None,
+        };
+        if let Some(players) = players_menu(screen, keymap, settings, variant.title) {
+            return Some(Game::of_variant(settings, players, variant));
+        }
+    }
+}
+
+//the odds games and horde, then who plays. None if the player backed out
+fn setups_menu(screen: &mut Screen, keymap: &Keymap, settings: &Settings) -> Option<Game> {
+    let mut items: Vec<Item> = setups::ALL
+        .iter()
+        .map(|setup| Item::new(setup.title))
+        .collect();
+    items.push(Item::new("Back"));
+    let mut selected = 0;
+    loop {
+        let setup: &Setup = match choose(
+            screen,
+            keymap,
+            "New game: odds and horde",
+            &items,
+            &mut selected,
+            &[],
+        ) {
+            Some(index) if index < setups::ALL.len() => &setups::ALL[index],
+            _ => return None,
+        };
+        if let Some(players) = players_menu(screen, keymap, settings, setup.title) {
+            return Some(Game::of_setup(settings, players, setup));
+        }
+    }
+}
+
+//who plays a game of `title`, None if the player backed out
+fn players_menu(
+    screen: &mut Screen,
+    keymap: &Keymap,
+    settings: &Settings,
+    title: &str,
+) -> Option<[Player; 2]> {
+    let computer = Player::Computer(settings.ai_level);
+    let items = [
+        Item::new("Human vs human"),
+        Item::new("Play white vs computer"),
+        Item::new("Play black vs computer"),
+        Item::new("Back"),
+    ];
+    let mut selected = 0;
+    match choose(screen, keymap, title, &items, &mut selected, &[]) {
+        Some(0) => Some([Player::Human, Player::Human]),
+        Some(1) => Some([Player::Human, computer]),
+        Some(2) => Some([computer, Player::Human]),
+        _ => None,
+    }
+}
+
+//lists the saved games, None if the player backed out
+fn load_menu(screen: &mut Screen, keymap: &Keymap) -> Option<Result<Game, String>> {
+    let saves = game::saved_games();
+    let mut items: Vec<Item> = saves
+        .iter()
+        .map(|path| Item::new(&path.file_stem().unwrap_or_default().to_string_lossy()))
+        .collect();
+    if items.is_empty() {
+        items.push(Item {
+            label: String::from("No saved games yet"),
+            enabled: false,
+        });
+    }
+    items.push(Item::new("Back"));
+    let mut selected = if saves.is_empty() { 1 } else { 0 };
+    match choose(screen, keymap, "Load game", &items, &mut selected, &[]) {
+        Some(index) if index < saves.len() => Some(Game::load(&saves[index])),
+        _ => None,
+    }
+}
+
+//asked when the player quits in the middle of a game
+pub fn leave_game(screen: &mut Screen, keymap: &Keymap, errors: &[String]) -> Leave {
+    let items = [
+        Item::new("Keep playing"),
+        Item::new("Save game and leave"),
+        Item::new("Leave (the game is still kept as the last game)"),
+    ];
+    let mut selected = 0;
+    match choose(screen, keymap, "Leave game?", &items, &mut selected, errors) {
+        Some(1) => Leave::Save,
+        Some(2) => Leave::Quit,
+        _ => Leave::KeepPlaying,
+    }
+}
+
+//hides the board between fog of war moves until the next player has the keyboard
+pub fn pass_turn(screen: &mut Screen, keymap: &Keymap, colour: board::Colour) {
+    let title = format!("Pass to {}", colour.name());
+    let items = [Item::new(&format!(
+        "I am {}, show the board",
+        colour.name()
+    ))];
+    let mut selected = 0;
+    choose(screen, keymap, &title, &items, &mut selected, &[]);
+}
+
+//every change is saved as soon as the player leaves the screen
+fn settings_menu(
+    screen: &mut Screen,
+    theme: &mut Theme,
+    keymap: &mut Keymap,
+    settings: &mut Settings,
+) {
+    let mut selected = 0;
+    let mut errors = Vec::new();
+    loop {
+        let time_control = match settings.time_control {
+            Some(control) => control.to_string(),
+            None => String::from("none"),
+        };
+        let items = [
+            Item::new(&format!("Colour theme: {}", theme.palette_name())),
+            Item::new(&format!("Pieces: {}", theme.pieces_name())),
+            Item::new(&format!(
+                "Board: {} at the top",
+                if settings.flipped { "black" } else { "white" }
+            )),
+            Item::new(&format!("Time control: {}", time_control)),
+            Item::new(&format!("Computer level: {}", settings.ai_level)),
+            Item::new(&format!(
+                "Bell: {}",
+                if settings.bell { "on" } else { "off" }
+            )),
+            Item::new(&format!("Reload keymap: {}", settings.keymap)),
+            Item::new("Back"),
+        ];
+        match choose(screen, keymap, "Settings", &items, &mut selected, &errors) {
+            Some(0) => {
+                theme.next_palette();
+                settings.theme = theme.palette_name().to_string();
+            }
+            Some(1) => {
+                theme.next_pieces();
+                settings.pieces = theme.pieces_name().to_string();
+            }
+            Some(2) => settings.flipped = !settings.flipped,
+            Some(3) => settings.time_control = next_time_control(settings.time_control),
+            Some(4) => settings.ai_level = settings.ai_level % MAX_AI_LEVEL + 1,
+            Some(5) => settings.bell = !settings.bell,
+            Some(6) => {
+                let (loaded, error) = Keymap::load(settings);
+                *keymap = loaded;
+                errors = error.into_iter().collect();
+            }
+            _ => {
+                //stay on the screen so the player sees why it didn't save
+                match settings.save() {
+                    Ok(()) => return,
+                    Err(error) if !errors.contains(&error) => errors = vec![error],
+                    Err(_) => return,
+                }
+            }
+        }
+    }
+}
+
+//no clock, then each preset in turn
+fn next_time_control(current: Option<TimeControl>) -> Option<TimeControl> {
+    let presets = TimeControl::PRESETS;
+    match current.and_then(|control| presets.iter().position(|preset| *preset == control)) {
+        Some(index) if index + 1 < presets.len() => Some(presets[index + 1]),
+        Some(_) => None,
+        None if current.is_some() => None,
+        None => Some(presets[0]),
+    }
+}
+
+//shows every keybind until any key is pressed
+fn keybinds(screen: &mut Screen, keymap: &Keymap) {
+    loop {
+        let mut frame = screen.frame();
+        let bottom = frame.height;
+        let row = draw_keybinds(&mut frame, keymap, 2, 1, bottom);
+        frame.print(
+            2,
+            row + 1,
+            "Press any key to go back",
+            Style::fg(Color::Blue),
+        );
+        if screen.draw(frame).is_err() {
+            return;
+        }
+        match read() {
+            Ok(Event::Key(key_event)) if key_event.kind != KeyEventKind::Release => return,
+            Ok(Event::Mouse(mouse_event))
+                if mouse_event.kind == MouseEventKind::Down(MouseButton::Left) =>
+            {
+                return
+            }
+            Ok(Event::Resize(_, _)) => screen.invalidate(),
+            Ok(_) => {}
+            Err(_) => return,
+        }
+    }
+}
+
+//a list the player moves through with the same keys used to move the arrow in
+//game, picking an item with select or move, or by clicking it. quit backs out.
+//returns the index of the picked item, None if the player backed out
+fn choose(
+    screen: &mut Screen,
+    keymap: &Keymap,
+    title: &str,
+    items: &[Item],
+    selected: &mut usize,
+    errors: &[String],
+) -> Option<usize> {
+    let top = ITEMS_TOP + errors.len() as u16;
+    loop {
+        let mut frame = screen.frame();
+        frame.print(2, 1, title, Style::fg(Color::Blue));
+        for (row, error) in errors.iter().enumerate() {
+            let line = format!("Error: {}", error);
+            frame.print(2, 2 + row as u16, &line, Style::fg(Color::Red));
+        }
+        for (row, item) in items.iter().enumerate() {
+            let y = top + row as u16;
+            let style = if !item.enabled {
+                Style::fg(Color::DarkGrey)
+            } else if row == *selected {
+                Style {
+                    foreground: Some(Color::Red),
+                    bold: true,
+                    ..Style::default()
+                }
+            } else {
+                Style::default()
+            };
+            if row == *selected {
+                frame.print(2, y, ">", style);
+            }
+            frame.print(4, y, &item.label, style);
+        }
+        let hint = format!(
+            "{}/{} to choose, {} to pick, {} to go back",
+            keymap.key_for(Action::Up),
+            keymap.key_for(Action::Down),
+            keymap.key_for(Action::Move),
+            keymap.key_for(Action::Quit)
+        );
+        frame.print(
+            2,
+            top + items.len() as u16 + 1,
+            &hint,
+            Style::fg(Color::Blue),
+        );
+        if screen.draw(frame).is_err() {
+            return None;
+        }
+
+        match read() {
+            Ok(Event::Key(key_event)) if key_event.kind != KeyEventKind::Release => {
+                match keymap.action(key_event) {
+                    Some(Action::Up) | Some(Action::Left) => *selected = step(items, *selected, -1),
+                    Some(Action::Down) | Some(Action::Right) => {
+                        *selected = step(items, *selected, 1)
+                    }
+                    Some(Action::Move) | Some(Action::Select) if items[*selected].enabled => {
+                        return Some(*selected)
+                    }
+                    Some(Action::Quit) => return None,
+                    _ => {}
+                }
+            }
+            Ok(Event::Mouse(mouse_event))
+                if mouse_event.kind == MouseEventKind::Down(MouseButton::Left) =>
+            {
+                let row = mouse_event.row.wrapping_sub(top) as usize;
+                if row < items.len() && items[row].enabled {
+                    *selected = row;
+                    return Some(row);
+                }
+            }
+            Ok(Event::Resize(_, _)) => screen.invalidate(),
+            Ok(_) => {}
+            Err(_) => return None,
+        }
+    }
+}
+
+//moves the selection, skipping items that can't be picked and wrapping around the ends
+fn step(items: &[Item], selected: usize, direction: i32) -> usize {
+    let count = items.len() as i32;
+    let mut index = selected as i32;
+    for _ in 0..count {
+        index = (index + direction).rem_euclid(count);
+        if items[index as usize].enabled {
+            return index as usize;
+        }
+    }
+    selected
+}
+
+//generated from the keymap so it always matches what the keys really do.
+//lines that would reach `bottom` are left out. returns the row after the last line drawn
+pub fn draw_keybinds(frame: &mut Frame, keymap: &Keymap, left: u16, top: u16, bottom: u16) -> u16 {
+    let mut lines: Vec<(String, String)> = Action::ALL
+        .iter()
+        .map(|action| (action.description().to_string(), keymap.keys_for(*action)))
+        .collect();
+    lines.push((
+        String::from("Mouse"),
+        String::from("click a piece, then click or drag to its new square"),
+    ));
+
+    frame.print(left, top, "Useful Keybinds: ", Style::default());
+    let mut row = top + 1;
+    for (description, keys) in lines {
+        if row >= bottom {
+            break;
+        }
+        let label = format!("  {}: ", description);
+        frame.print(left, row, &label, Style::default());
+        frame.print(left + label.len() as u16, row, &keys, Style::fg(Color::Red));
+        row += 1;
+    }
+    row
+}
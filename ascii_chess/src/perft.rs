@@ -0,0 +1,293 @@
+use crate::bitboard::Position;
+use crate::board::{Matrix, Move};
+use std::time::Instant;
+
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+//counts every sequence of legal moves `depth` plies deep. the numbers for
+//well known positions are published, so any difference is a move generator bug.
+//boards the bitboards can't hold are counted on the array board
+pub fn perft(board: &Matrix, depth: u32) -> u64 {
+    if !board.fits_bitboards() {
+        return scan_perft(board, depth);
+    }
+    Position::from(board).perft(depth)
+}
+
+//perft on the array board, only here to compare against the bitboards
+pub fn scan_perft(board: &Matrix, depth: u32) -> u64 {
+    scan_nodes(&mut board.clone(), depth)
+}
+
+//moves are made and taken back on the one board all the way down
+fn scan_nodes(board: &mut Matrix, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+    let moves = board.scan_legal_moves();
+    if depth == 1 {
+        return moves.len() as u64;
+    }
+    let mut nodes = 0;
+    for candidate in moves {
+        let undo = board.make_move(candidate);
+        nodes += scan_nodes(board, depth - 1);
+        board.unmake_move(undo);
+    }
+    nodes
+}
+
+//perft split by the first move, to narrow down which move a wrong count comes from.
+//at depth 0 no move is played, so there is nothing to split
+pub fn divide(board: &Matrix, depth: u32) -> Vec<(Move, u64)> {
+    let mut split = Vec::new();
+    if depth == 0 {
+        return split;
+    }
+    if !board.fits_bitboards() {
+        for candidate in board.legal_moves() {
+            let mut after = board.clone();
+            after.make_move(candidate);
+            split.push((candidate, scan_perft(&after, depth - 1)));
+        }
+        return split;
+    }
+    let position = Position::from(board);
+    for candidate in position.legal_moves() {
+        let mut after = position;
+        after.make_move(candidate);
+        split.push((candidate, after.perft(depth - 1)));
+    }
+    split
+}
+
+//`ChessAscii perft <depth> [fen]`, prints the count under every first move and the total
+pub fn run(args: &[String]) -> Result<(), String> {
+    let depth: u32 = match args.first() {
+        Some(depth) => depth
+            .parse()
+            .map_err(|_| format!("depth must be a number, not `{}`", depth))?,
+        None => return Err(String::from("usage: perft <depth> [fen]")),
+    };
+    if depth == 0 {
+        return Err(String::from("depth must be at least 1"));
+    }
+    let fen = if args.len() > 1 {
+        args[1..].join(" ")
+    } else {
+        String::from(START_FEN)
+    };
+    let board = Matrix::from_fen(&fen)?;
+
+    let start = Instant::now();
+    let mut total = 0;
+    for (candidate, nodes) in divide(&board, depth) {
+        println!("{}: {}", candidate.name(), nodes);
+        total += nodes;
+    }
+    let seconds = start.elapsed().as_secs_f64();
+    println!();
+    println!("Nodes: {}", total);
+    println!(
+        "Time: {:.3}s ({:.0} nodes/s)",
+        seconds,
+        total as f64 / seconds.max(1e-9)
+    );
+    Ok(())
+}
+
+//the positions perft is usually checked on
+pub const REFERENCE_POSITIONS: [(&str, &str); 7] = [
+    ("start position", START_FEN),
+    (
+        "kiwipete",
+        "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    ),
+    ("position 3", "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1"),
+    (
+        "position 4",
+        "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    ),
+    (
+        "position 5",
+        "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    ),
+    (
+        "position 6",
+        "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    ),
+    (
+        "chess960",
+        "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+    ),
+];
+
+//`ChessAscii bench [depth]`, times perft on the reference positions with the
+//array board and with bitboards. build with --release for meaningful numbers
+pub fn bench(args: &[String]) -> Result<(), String> {
+    let depth: u32 = match args.first() {
+        Some(depth) => depth
+            .parse()
+            .map_err(|_| format!("depth must be a number, not `{}`", depth))?,
+        None => 4,
+    };
+    println!(
+        "{:<16}{:>12}{:>12}{:>12}{:>10}",
+        "position", "nodes", "array", "bitboard", "speedup"
+    );
+    for (name, fen) in REFERENCE_POSITIONS {
+        let board = Matrix::from_fen(fen)?;
+        let start = Instant::now();
+        let scanned = scan_perft(&board, depth);
+        let array_time = start.elapsed().as_secs_f64();
+        let start = Instant::now();
+        let nodes = perft(&board, depth);
+        let bitboard_time = start.elapsed().as_secs_f64();
+        if nodes != scanned {
+            return Err(format!(
+                "{}: array board counted {} but bitboards {}",
+                name, scanned, nodes
+            ));
+        }
+        println!(
+            "{:<16}{:>12}{:>11.3}s{:>11.3}s{:>9.1}x",
+            name,
+            nodes,
+            array_time,
+            bitboard_time,
+            array_time / bitboard_time.max(1e-9)
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::variants;
+
+    //reference counts from https://www.chessprogramming.org/Perft_Results
+    fn check(fen: &str, expected: &[u64]) {
+        let board = Matrix::from_fen(fen).unwrap();
+        for (depth, nodes) in expected.iter().enumerate() {
+            assert_eq!(
+                perft(&board, depth as u32 + 1),
+                *nodes,
+                "depth {} of {}",
+                depth + 1,
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn start_position() {
+        check(START_FEN, &[20, 400, 8902, 197281]);
+    }
+
+    #[test]
+    fn depth_0_has_no_split() {
+        let board = Matrix::from_fen(START_FEN).unwrap();
+        assert!(divide(&board, 0).is_empty());
+        assert!(run(&[String::from("0")]).is_err());
+    }
+
+    #[test]
+    fn kiwipete() {
+        check(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862],
+        );
+    }
+
+    #[test]
+    fn position_3() {
+        check(
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238],
+        );
+    }
+
+    #[test]
+    fn position_4() {
+        check(
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_4_mirrored() {
+        check(
+            "r2q1rk1/pP1p2pp/Q4n2/bbp1p3/Np6/1B3NBn/pPPP1PPP/R3K2R b KQ - 0 1",
+            &[6, 264, 9467],
+        );
+    }
+
+    #[test]
+    fn position_5() {
+        check(
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        );
+    }
+
+    #[test]
+    fn position_6() {
+        check(
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        );
+    }
+
+    #[test]
+    fn chess960() {
+        check(
+            "bqnb1rkr/pp3ppp/3ppn2/2p5/5P2/P2P4/NPP1P1PP/BQ1BNRKR w HFhf - 2 9",
+            &[21, 528, 12189],
+        );
+    }
+
+    //10x8, only the array board can count it
+    #[test]
+    fn capablanca() {
+        check(
+            "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1",
+            &[28, 784, 25228],
+        );
+    }
+
+    //the horde's first row pawns double step too, counts as lichess gives them
+    #[test]
+    fn horde() {
+        let fen = "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1";
+        let board = Matrix::from_fen_in(fen, Some(&variants::HORDE)).unwrap();
+        for (depth, nodes) in [8, 128, 1274, 23310].iter().enumerate() {
+            assert_eq!(
+                perft(&board, depth as u32 + 1),
+                *nodes,
+                "depth {}",
+                depth + 1
+            );
+        }
+    }
+
+    //the array generator is still used for checking, so it has to stay right too
+    #[test]
+    fn array_board_agrees_with_bitboards() {
+        for (name, fen) in REFERENCE_POSITIONS {
+            let board = Matrix::from_fen(fen).unwrap();
+            for (candidate, nodes) in divide(&board, 2) {
+                let mut after = board.clone();
+                after.make_move(candidate);
+                assert_eq!(
+                    scan_perft(&after, 1),
+                    nodes,
+                    "{} after {}",
+                    name,
+                    candidate.name()
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use crate::board::{self, Colour, Matrix, Move, Piece};
+use crate::game::{Game, Player};
+use crate::perft::START_FEN;
+use crate::variants;
+use std::fs;
+use std::path::Path;
+
+//PGN export format keeps movetext lines under 80 characters
+const LINE_WIDTH: usize = 79;
+
+//the game as PGN, with a Variant tag for anything but standard chess and the
+//start position as a FEN tag when the game didn't begin from the usual one
+pub fn export(game: &Game) -> String {
+    let start = game
+        .start
+        .as_deref()
+        .map(|fen| Matrix::from_fen_in(fen, Some(game.board.variant)));
+    let mut board = match start {
+        Some(Ok(board)) => board,
+        //without the start the moves can't be replayed, so only the position is kept
+        _ => game.board.clone(),
+    };
+    let replay = game.start.is_some();
+    //a FEN only tells the board size, the rules come from the game
+    board.variant = game.board.variant;
+    board.chess960 = game.board.chess960;
+
+    let result = result(game);
+    let mut text = String::new();
+    let mut tag = |name: &str, value: &str| {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "'")));
+    };
+    tag("Event", "Casual game");
+    tag("Site", "ascii chess");
+    tag("Date", "????.??.??");
+    tag("Round", "-");
+    tag("White", &player_name(game.players[0]));
+    tag("Black", &player_name(game.players[1]));
+    tag("Result", result);
+    if board.variant.name != variants::STANDARD.name {
+        tag("Variant", board.variant.tag);
+    } else if board.chess960 {
+        tag("Variant", "Chess960");
+    }
+    let fen = board.to_fen();
+    //three-check's check counts are left out of the comparison
+    if !fen
+        .split_whitespace()
+        .take(6)
+        .eq(START_FEN.split_whitespace())
+    {
+        tag("SetUp", "1");
+        tag("FEN", &fen);
+    }
+    text.push('\n');
+
+    let mut words = Vec::new();
+    if replay {
+        for (index, name) in game.moves.iter().enumerate() {
+            let chosen = match Move::parse(name) {
+                Some(chosen) if board.legal_moves().contains(&chosen) => chosen,
+                _ => break,
+            };
+            if board.turn == Colour::White {
+                words.push(format!("{}.", board.fullmove));
+            } else if index == 0 {
+                words.push(format!("{}...", board.fullmove));
+            }
+            words.push(san(&board, chosen));
+            board.make_move(chosen);
+        }
+    }
+    words.push(String::from(result));
+
+    let mut line = String::new();
+    for word in words {
+        if !line.is_empty() && line.len() + 1 + word.len() > LINE_WIDTH {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&word);
+    }
+    text.push_str(&line);
+    text.push('\n');
+    text
+}
+
+pub fn write(game: &Game, path: &Path) -> Result<(), String> {
+    fs::write(path, export(game)).map_err(|error| format!("{}: {}", path.display(), error))
+}
+
+//a move in standard algebraic notation, like Nbd2, exd6, e8=Q+ or O-O
+pub fn san(board: &Matrix, chosen: Move) -> String {
+    let (piece, _) = board.piece_at(chosen.from);
+    let mut text = match board.castling_wing(chosen.from, chosen.to) {
+        //crazyhouse drops, the same as in long algebraic
+        _ if chosen.drop.is_some() => chosen.name(),
+        Some(0) => String::from("O-O"),
+        Some(_) => String::from("O-O-O"),
+        None if piece == Piece::Pawn => {
+            let mut text = String::new();
+            //a pawn moving sideways takes, en passant too
+            if chosen.from.1 != chosen.to.1 {
+                text.push(board::square_name(chosen.from).remove(0));
+                text.push('x');
+            }
+            text.push_str(&board::square_name(chosen.to));
+            if let Some(promotion) = chosen.promotion {
+                text.push('=');
+                text.push(promotion.letter().to_ascii_uppercase());
+            }
+            text
+        }
+        None => {
+            let mut text = String::from(piece.letter().to_ascii_uppercase());
+            text.push_str(&disambiguation(board, chosen));
+            if board.piece_at(chosen.to).0 != Piece::Null {
+                text.push('x');
+            }
+            text.push_str(&board::square_name(chosen.to));
+            text
+        }
+    };
+    let mut after = board.clone();
+    after.make_move(chosen);
+    if after.variant_win().is_some()
+        || (after.in_check(after.turn) && after.legal_moves().is_empty())
+    {
+        text.push('#');
+    } else if after.in_check(after.turn) {
+        text.push('+');
+    }
+    text
+}
+
+//the file, rank or both of the square the piece comes from, when another piece
+//of the same kind could go to the same square
+fn disambiguation(board: &Matrix, chosen: Move) -> String {
+    let piece = board.piece_at(chosen.from).0;
+    let others: Vec<(usize, usize)> = board
+        .legal_moves()
+        .iter()
+        .filter(|other| {
+            other.to == chosen.to
+                && other.from != chosen.from
+                && board.piece_at(other.from).0 == piece
+        })
+        .map(|other| other.from)
+        .collect();
+    let name = board::square_name(chosen.from);
+    let (file, rank) = name.split_at(1);
+    if others.is_empty() {
+        String::new()
+    } else if others.iter().all(|other| other.1 != chosen.from.1) {
+        String::from(file)
+    } else if others.iter().all(|other| other.0 != chosen.from.0) {
+        String::from(rank)
+    } else {
+        name
+    }
+}
+
+//1-0, 0-1 or 1/2-1/2 once the game is over, * while it goes on
+fn result(game: &Game) -> &'static str {
+    let board = &game.board;
+    let winner = match board.variant_win() {
+        Some(winner) => Some(winner),
+        None if game.repetitions() >= 3 => None,
+        None if !board.legal_moves().is_empty() => return "*",
+        None if board.in_check(board.turn) => Some(board.turn.opponent()),
+        None => None,
+    };
+    match winner {
+        Some(Colour::White) => "1-0",
+        Some(_) => "0-1",
+        None => "1/2-1/2",
+    }
+}
+
+fn player_name(player: Player) -> String {
+    match player {
+        Player::Human => String::from("Human"),
+        Player::Computer(level) => format!("Computer level {}", level),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sans(fen: &str, moves: &[&str]) -> Vec<String> {
+        let mut board = Matrix::from_fen(fen).unwrap();
+        let mut names = Vec::new();
+        for name in moves {
+            let chosen = Move::parse(name).unwrap();
+            names.push(san(&board, chosen));
+            board.make_move(chosen);
+        }
+        names
+    }
+
+    #[test]
+    fn writes_standard_algebraic() {
+        assert_eq!(
+            sans(
+                START_FEN,
+                &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5", "g8f6", "e1g1"]
+            ),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "Nf6", "O-O"]
+        );
+        assert_eq!(sans("4k3/8/8/8/8/8/4K3/R6R w - - 0 1", &["a1d1"]), ["Rad1"]);
+        assert_eq!(
+            sans("4k3/P7/8/8/8/8/8/4K3 w - - 0 1", &["a7a8q"]),
+            ["a8=Q+"]
+        );
+        assert_eq!(
+            sans("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1", &["a1a8"]),
+            ["Ra8#"]
+        );
+    }
+}
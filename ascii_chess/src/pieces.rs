@@ -0,0 +1,278 @@
+use crate::board::Piece;
+
+//how one kind of piece moves. offsets are (rows, columns) seen from white's side,
+//black's are mirrored top to bottom, so a new piece only has to list its moves
+//here and the board's move generator, check detection and move errors follow
+pub trait PieceRules {
+    //squares jumped to directly, whatever is in between, moving or taking
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &[]
+    }
+
+    //directions walked one square at a time until something is in the way
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &[]
+    }
+
+    //steps onto an empty square only, the way a pawn pushes
+    fn pushes(&self) -> &'static [(i32, i32)] {
+        &[]
+    }
+
+    //steps that have to take something, the way a pawn takes
+    fn takes(&self) -> &'static [(i32, i32)] {
+        &[]
+    }
+
+    //pushes twice from its starting row, takes en passant and resets the 50 move
+    //count whenever it moves
+    fn pawn_like(&self) -> bool {
+        false
+    }
+
+    //becomes one of the variant's promotion pieces on the last row
+    fn promotes(&self) -> bool {
+        false
+    }
+
+    //castles with a rook and mustn't be left in check
+    fn royal(&self) -> bool {
+        false
+    }
+
+    //worth in centipawns, for the computer
+    fn value(&self) -> i32 {
+        0
+    }
+}
+
+pub struct Pawn;
+pub struct Knight;
+pub struct Bishop;
+pub struct Rook;
+pub struct Queen;
+pub struct King;
+//fairy pieces, each a mix of leaps and slides: the archbishop is a bishop and a
+//knight, the chancellor a rook and a knight, the amazon a queen and a knight,
+//the camel leaps one square further than a knight and the nightrider makes
+//knight jumps over and over in the same direction
+pub struct Archbishop;
+pub struct Chancellor;
+pub struct Amazon;
+pub struct Camel;
+pub struct Nightrider;
+//an empty square, nothing to move
+pub struct Nothing;
+
+pub const KNIGHT_OFFSETS: [(i32, i32); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+//the eight squares around, which are also the queen's directions
+pub const KING_OFFSETS: [(i32, i32); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+pub const CAMEL_OFFSETS: [(i32, i32); 8] = [
+    (1, 3),
+    (3, 1),
+    (3, -1),
+    (1, -3),
+    (-1, -3),
+    (-3, -1),
+    (-3, 1),
+    (-1, 3),
+];
+pub const DIAGONALS: [(i32, i32); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+pub const STRAIGHTS: [(i32, i32); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+
+impl PieceRules for Pawn {
+    fn pushes(&self) -> &'static [(i32, i32)] {
+        &[(1, 0)]
+    }
+
+    fn takes(&self) -> &'static [(i32, i32)] {
+        &[(1, -1), (1, 1)]
+    }
+
+    fn pawn_like(&self) -> bool {
+        true
+    }
+
+    fn promotes(&self) -> bool {
+        true
+    }
+
+    fn value(&self) -> i32 {
+        100
+    }
+}
+
+impl PieceRules for Knight {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &KNIGHT_OFFSETS
+    }
+
+    fn value(&self) -> i32 {
+        320
+    }
+}
+
+impl PieceRules for Bishop {
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &DIAGONALS
+    }
+
+    fn value(&self) -> i32 {
+        330
+    }
+}
+
+impl PieceRules for Rook {
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &STRAIGHTS
+    }
+
+    fn value(&self) -> i32 {
+        500
+    }
+}
+
+impl PieceRules for Queen {
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &KING_OFFSETS
+    }
+
+    fn value(&self) -> i32 {
+        900
+    }
+}
+
+impl PieceRules for King {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &KING_OFFSETS
+    }
+
+    fn royal(&self) -> bool {
+        true
+    }
+}
+
+impl PieceRules for Archbishop {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &KNIGHT_OFFSETS
+    }
+
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &DIAGONALS
+    }
+
+    fn value(&self) -> i32 {
+        850
+    }
+}
+
+impl PieceRules for Chancellor {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &KNIGHT_OFFSETS
+    }
+
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &STRAIGHTS
+    }
+
+    fn value(&self) -> i32 {
+        900
+    }
+}
+
+impl PieceRules for Amazon {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &KNIGHT_OFFSETS
+    }
+
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &KING_OFFSETS
+    }
+
+    fn value(&self) -> i32 {
+        1300
+    }
+}
+
+impl PieceRules for Camel {
+    fn leaps(&self) -> &'static [(i32, i32)] {
+        &CAMEL_OFFSETS
+    }
+
+    fn value(&self) -> i32 {
+        250
+    }
+}
+
+impl PieceRules for Nightrider {
+    fn slides(&self) -> &'static [(i32, i32)] {
+        &KNIGHT_OFFSETS
+    }
+
+    fn value(&self) -> i32 {
+        550
+    }
+}
+
+impl PieceRules for Nothing {}
+
+impl Piece {
+    pub fn rules(&self) -> &'static dyn PieceRules {
+        match self {
+            Piece::Pawn => &Pawn,
+            Piece::Knight => &Knight,
+            Piece::Bishop => &Bishop,
+            Piece::Rook => &Rook,
+            Piece::Queen => &Queen,
+            Piece::King => &King,
+            Piece::Archbishop => &Archbishop,
+            Piece::Chancellor => &Chancellor,
+            Piece::Amazon => &Amazon,
+            Piece::Camel => &Camel,
+            Piece::Nightrider => &Nightrider,
+            Piece::Null => &Nothing,
+        }
+    }
+}
+
+//every kind of piece that can stand on the board
+pub const ALL: [Piece; 11] = [
+    Piece::Pawn,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Rook,
+    Piece::Queen,
+    Piece::King,
+    Piece::Archbishop,
+    Piece::Chancellor,
+    Piece::Amazon,
+    Piece::Camel,
+    Piece::Nightrider,
+];
+
+//the pieces the bitboards and polyglot keys don't know about
+pub const FAIRY: [Piece; 5] = [
+    Piece::Archbishop,
+    Piece::Chancellor,
+    Piece::Amazon,
+    Piece::Camel,
+    Piece::Nightrider,
+];
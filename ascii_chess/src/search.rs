@@ -0,0 +1,625 @@
+use crate::bitboard::{self, Position, BISHOP, KING, KNIGHT, PAWN, QUEEN, ROOK, WHITE};
+use crate::board::{Colour, Matrix, Move, Piece, POCKET};
+use crate::variants::Rules;
+use std::time::{Duration, Instant};
+
+//scores are in centipawns from the side to move's point of view
+const MATE: i32 = 30_000;
+const INFINITY: i32 = 32_000;
+const MAX_PLY: usize = 64;
+//entries in the transposition table, a power of two so the key can be masked.
+//an entry is 12 bytes, so the table takes 12 MiB
+const TABLE_SIZE: usize = 1 << 20;
+
+const PIECE_VALUES: [i32; 6] = [100, 320, 330, 500, 900, 0];
+
+//piece square tables from white's side, written as the board looks with the
+//eighth rank on top. black reads them upside down
+#[rustfmt::skip]
+const PIECE_SQUARES: [[i32; 64]; 6] = [
+    [
+         0,  0,  0,  0,  0,  0,  0,  0,
+        50, 50, 50, 50, 50, 50, 50, 50,
+        10, 10, 20, 30, 30, 20, 10, 10,
+         5,  5, 10, 25, 25, 10,  5,  5,
+         0,  0,  0, 20, 20,  0,  0,  0,
+         5, -5,-10,  0,  0,-10, -5,  5,
+         5, 10, 10,-20,-20, 10, 10,  5,
+         0,  0,  0,  0,  0,  0,  0,  0,
+    ],
+    [
+        -50,-40,-30,-30,-30,-30,-40,-50,
+        -40,-20,  0,  0,  0,  0,-20,-40,
+        -30,  0, 10, 15, 15, 10,  0,-30,
+        -30,  5, 15, 20, 20, 15,  5,-30,
+        -30,  0, 15, 20, 20, 15,  0,-30,
+        -30,  5, 10, 15, 15, 10,  5,-30,
+        -40,-20,  0,  5,  5,  0,-20,-40,
+        -50,-40,-30,-30,-30,-30,-40,-50,
+    ],
+    [
+        -20,-10,-10,-10,-10,-10,-10,-20,
+        -10,  0,  0,  0,  0,  0,  0,-10,
+        -10,  0,  5, 10, 10,  5,  0,-10,
+        -10,  5,  5, 10, 10,  5,  5,-10,
+        -10,  0, 10, 10, 10, 10,  0,-10,
+        -10, 10, 10, 10, 10, 10, 10,-10,
+        -10,  5,  0,  0,  0,  0,  5,-10,
+        -20,-10,-10,-10,-10,-10,-10,-20,
+    ],
+    [
+         0,  0,  0,  0,  0,  0,  0,  0,
+         5, 10, 10, 10, 10, 10, 10,  5,
+        -5,  0,  0,  0,  0,  0,  0, -5,
+        -5,  0,  0,  0,  0,  0,  0, -5,
+        -5,  0,  0,  0,  0,  0,  0, -5,
+        -5,  0,  0,  0,  0,  0,  0, -5,
+        -5,  0,  0,  0,  0,  0,  0, -5,
+         0,  0,  0,  5,  5,  0,  0,  0,
+    ],
+    [
+        -20,-10,-10, -5, -5,-10,-10,-20,
+        -10,  0,  0,  0,  0,  0,  0,-10,
+        -10,  0,  5,  5,  5,  5,  0,-10,
+         -5,  0,  5,  5,  5,  5,  0, -5,
+          0,  0,  5,  5,  5,  5,  0, -5,
+        -10,  5,  5,  5,  5,  5,  0,-10,
+        -10,  0,  5,  0,  0,  0,  0,-10,
+        -20,-10,-10, -5, -5,-10,-10,-20,
+    ],
+    [
+        -30,-40,-40,-50,-50,-40,-40,-30,
+        -30,-40,-40,-50,-50,-40,-40,-30,
+        -30,-40,-40,-50,-50,-40,-40,-30,
+        -30,-40,-40,-50,-50,-40,-40,-30,
+        -20,-30,-30,-40,-40,-30,-30,-20,
+        -10,-20,-20,-20,-20,-20,-20,-10,
+         20, 20,  0,  0,  0,  0, 20, 20,
+         20, 30, 10,  0,  0, 10, 30, 20,
+    ],
+];
+
+//how far the computer looks and how long it may take, for each level in the settings
+const LEVELS: [(i32, u64); 5] = [
+    (1, 100),
+    (2, 250),
+    (3, 500),
+    (6, 800),
+    (MAX_PLY as i32, 1000),
+];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower, //the real score is at least this, the search failed high
+    Upper, //the real score is at most this, the search failed low
+}
+
+#[derive(Clone, Copy)]
+struct Entry {
+    check: u32, //the top half of the key, the bottom bits already picked the slot
+    best: u16,  //packed by pack_move, 0 when there is none
+    depth: i8,
+    bound: Bound,
+    score: i32,
+}
+
+//what the last search found and how hard it worked, for the debug overlay
+#[derive(Clone, Copy, Default)]
+pub struct Report {
+    pub best: Option<Move>,
+    pub score: i32,
+    pub depth: i32,
+    pub nodes: u64,
+    pub table_hits: u64,
+    pub elapsed: Duration,
+}
+
+impl Report {
+    pub fn nodes_per_second(&self) -> u64 {
+        (self.nodes as f64 / self.elapsed.as_secs_f64().max(1e-6)) as u64
+    }
+}
+
+//iterative deepening alpha-beta. the table and the move ordering heuristics are
+//kept between moves, the table because positions come up again and the rest
+//because it is cheap
+pub struct Search {
+    table: Vec<Option<Entry>>,
+    killers: [[Option<Move>; 2]; MAX_PLY],
+    history: Box<[[u32; 64]; 64]>,
+    path: Vec<u64>, //keys of the game so far and then the line being searched
+    root_best: Option<Move>,
+    nodes: u64,
+    table_hits: u64,
+    deadline: Instant,
+    stopped: bool,
+}
+
+impl Search {
+    pub fn new() -> Search {
+        Search {
+            table: vec![None; TABLE_SIZE],
+            killers: [[None; 2]; MAX_PLY],
+            history: Box::new([[0; 64]; 64]),
+            path: Vec::new(),
+            root_best: None,
+            nodes: 0,
+            table_hits: 0,
+            deadline: Instant::now(),
+            stopped: false,
+        }
+    }
+
+    //picks a move for the side to move at one of the levels from the settings.
+    //`previous` are the keys of the positions before this one, for repetitions
+    pub fn think(&mut self, position: &Position, previous: &[u64], level: u8) -> Report {
+        let (max_depth, millis) = LEVELS[(level.clamp(1, LEVELS.len() as u8) - 1) as usize];
+        self.search(position, previous, max_depth, Duration::from_millis(millis))
+    }
+
+    //for boards the bitboards can't hold, like ones with fairy pieces: plain
+    //alpha-beta on the array board counting material only. much slower, so it
+    //gets less deep in the same time, but the array generator knows every piece
+    pub fn think_on_board(&mut self, board: &Matrix, level: u8) -> Report {
+        let (max_depth, millis) = LEVELS[(level.clamp(1, LEVELS.len() as u8) - 1) as usize];
+        let start = Instant::now();
+        self.deadline = start + Duration::from_millis(millis);
+        self.stopped = false;
+        self.nodes = 0;
+        self.table_hits = 0;
+        let mut report = Report {
+            best: board.legal_moves().first().copied(),
+            ..Report::default()
+        };
+        //moves are made and taken back on one copy all the way down
+        let mut board = board.clone();
+        for depth in 1..=max_depth {
+            let score = self.negamax_on_board(&mut board, depth, 0, -INFINITY, INFINITY);
+            if self.stopped {
+                break;
+            }
+            report.best = self.root_best;
+            report.score = score;
+            report.depth = depth;
+            if score.abs() >= MATE - MAX_PLY as i32 {
+                break;
+            }
+        }
+        report.nodes = self.nodes;
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    fn negamax_on_board(
+        &mut self,
+        board: &mut Matrix,
+        depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(256) && Instant::now() >= self.deadline {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        //a win by the variant's own rules counts the same as mate
+        if let Some(winner) = board.variant_win() {
+            return if winner == board.turn {
+                MATE - ply as i32
+            } else {
+                -MATE + ply as i32
+            };
+        }
+        let mut moves = board.legal_moves();
+        if moves.is_empty() {
+            return if board.in_check(board.turn) {
+                -MATE + ply as i32
+            } else {
+                0
+            };
+        }
+        if depth == 0 || ply >= MAX_PLY - 1 {
+            return material(board);
+        }
+        //the biggest captures first, so the cutoffs come early
+        moves.sort_by_cached_key(|candidate| -board.piece_at(candidate.to).0.rules().value());
+        let mut best = -INFINITY;
+        for candidate in moves {
+            let undo = board.make_move(candidate);
+            let score = -self.negamax_on_board(board, depth - 1, ply + 1, -beta, -alpha);
+            board.unmake_move(undo);
+            if self.stopped {
+                return 0;
+            }
+            if score > best {
+                best = score;
+                if ply == 0 {
+                    self.root_best = Some(candidate);
+                }
+            }
+            alpha = alpha.max(score);
+            if alpha >= beta {
+                break;
+            }
+        }
+        best
+    }
+
+    pub fn search(
+        &mut self,
+        position: &Position,
+        previous: &[u64],
+        max_depth: i32,
+        limit: Duration,
+    ) -> Report {
+        let start = Instant::now();
+        self.deadline = start + limit;
+        self.stopped = false;
+        self.nodes = 0;
+        self.table_hits = 0;
+        self.killers = [[None; 2]; MAX_PLY];
+        for row in self.history.iter_mut() {
+            for score in row.iter_mut() {
+                *score /= 8;
+            }
+        }
+        self.path = previous.to_vec();
+
+        let mut report = Report {
+            best: position.legal_moves().first().copied(),
+            ..Report::default()
+        };
+        for depth in 1..=max_depth {
+            let score = self.negamax(position, depth, 0, -INFINITY, INFINITY);
+            //a search cut short is thrown away, the last finished depth is kept
+            if self.stopped {
+                break;
+            }
+            report.best = self.root_best;
+            report.score = score;
+            report.depth = depth;
+            if score.abs() >= MATE - MAX_PLY as i32 {
+                break;
+            }
+        }
+        report.nodes = self.nodes;
+        report.table_hits = self.table_hits;
+        report.elapsed = start.elapsed();
+        report
+    }
+
+    fn negamax(
+        &mut self,
+        position: &Position,
+        mut depth: i32,
+        ply: usize,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.nodes.is_multiple_of(2048) && Instant::now() >= self.deadline {
+            self.stopped = true;
+        }
+        if self.stopped {
+            return 0;
+        }
+        let key = position.hash();
+        if ply > 0 && self.path.contains(&key) {
+            return 0; //a repetition, treated as a draw
+        }
+        let in_check = position.checked();
+        //check extension, a check is never left as the last move seen
+        if in_check && ply < MAX_PLY / 2 {
+            depth += 1;
+        }
+        if depth <= 0 || ply >= MAX_PLY - 1 {
+            return self.quiescence(position, ply, alpha, beta);
+        }
+
+        let mut hash_move = None;
+        if let Some(entry) = self.probe(key) {
+            self.table_hits += 1;
+            hash_move = unpack_move(entry.best);
+            if ply > 0 && entry.depth as i32 >= depth {
+                let score = from_table(entry.score, ply);
+                let cutoff = match entry.bound {
+                    Bound::Exact => true,
+                    Bound::Lower => score >= beta,
+                    Bound::Upper => score <= alpha,
+                };
+                if cutoff {
+                    return score;
+                }
+            }
+        }
+
+        let mut moves = position.legal_moves();
+        if moves.is_empty() {
+            return if in_check { -MATE + ply as i32 } else { 0 };
+        }
+        self.order(position, &mut moves, hash_move, ply);
+
+        let original_alpha = alpha;
+        let mut best = -INFINITY;
+        let mut best_move = None;
+        self.path.push(key);
+        for candidate in moves {
+            let mut after = *position;
+            after.make_move(candidate);
+            let score = -self.negamax(&after, depth - 1, ply + 1, -beta, -alpha);
+            if self.stopped {
+                self.path.pop();
+                return 0;
+            }
+            if score > best {
+                best = score;
+                best_move = Some(candidate);
+                if ply == 0 {
+                    self.root_best = best_move;
+                }
+            }
+            if score > alpha {
+                alpha = score;
+            }
+            if alpha >= beta {
+                if !position.is_capture(candidate) {
+                    self.remember_quiet(candidate, ply, depth);
+                }
+                break;
+            }
+        }
+        self.path.pop();
+
+        let bound = if best >= beta {
+            Bound::Lower
+        } else if best > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        let entry = Entry {
+            check: (key >> 32) as u32,
+            best: best_move.map_or(0, pack_move),
+            depth: depth as i8,
+            bound,
+            score: to_table(best, ply),
+        };
+        self.store(key, entry);
+        best
+    }
+
+    //only captures and promotions are searched until the position is quiet, so a
+    //piece hanging at the end of the line isn't missed
+    fn quiescence(&mut self, position: &Position, ply: usize, mut alpha: i32, beta: i32) -> i32 {
+        self.nodes += 1;
+        let stand_pat = evaluate(position);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+        if ply >= MAX_PLY - 1 {
+            return alpha;
+        }
+        let mut moves: Vec<Move> = position
+            .legal_moves()
+            .into_iter()
+            .filter(|candidate| position.is_capture(*candidate) || candidate.promotion.is_some())
+            .collect();
+        self.order(position, &mut moves, None, ply);
+        for candidate in moves {
+            let mut after = *position;
+            after.make_move(candidate);
+            let score = -self.quiescence(&after, ply + 1, -beta, -alpha);
+            if score >= beta {
+                return score;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    //hash move first, then captures by most valuable victim and least valuable
+    //attacker, then the killer moves of this ply, then quiet moves by history
+    fn order(&self, position: &Position, moves: &mut [Move], hash_move: Option<Move>, ply: usize) {
+        moves.sort_by_cached_key(|candidate| {
+            let from = bitboard::square_of(candidate.from);
+            let to = bitboard::square_of(candidate.to);
+            let score = if Some(*candidate) == hash_move {
+                10_000_000
+            } else if position.is_capture(*candidate) {
+                let victim = position.kind_at(to).unwrap_or(PAWN);
+                let attacker = position.kind_at(from).unwrap_or(PAWN);
+                1_000_000 + PIECE_VALUES[victim] * 10 - PIECE_VALUES[attacker] / 10
+            } else if candidate.promotion.is_some() {
+                900_000
+            } else if self.killers[ply][0] == Some(*candidate) {
+                800_000
+            } else if self.killers[ply][1] == Some(*candidate) {
+                700_000
+            } else {
+                self.history[from][to].min(600_000) as i32
+            };
+            -score
+        });
+    }
+
+    //a quiet move that caused a cutoff is likely good in sibling positions too
+    fn remember_quiet(&mut self, candidate: Move, ply: usize, depth: i32) {
+        if self.killers[ply][0] != Some(candidate) {
+            self.killers[ply][1] = self.killers[ply][0];
+            self.killers[ply][0] = Some(candidate);
+        }
+        let from = bitboard::square_of(candidate.from);
+        let to = bitboard::square_of(candidate.to);
+        self.history[from][to] += (depth * depth) as u32;
+    }
+
+    fn probe(&self, key: u64) -> Option<Entry> {
+        self.table[key as usize & (TABLE_SIZE - 1)]
+            .filter(|entry| entry.check == (key >> 32) as u32)
+    }
+
+    //deeper results replace shallower ones in the same slot
+    fn store(&mut self, key: u64, entry: Entry) {
+        let slot = &mut self.table[key as usize & (TABLE_SIZE - 1)];
+        if slot.is_none_or(|old| old.check != entry.check || old.depth <= entry.depth) {
+            *slot = Some(entry);
+        }
+    }
+}
+
+//a move in 16 bits for the table: from and to square, then the piece it
+//promotes to as a bitboard kind, which is never 0 since pawns can't be one.
+//a1a1 isn't a move, so 0 is left for none
+fn pack_move(chosen: Move) -> u16 {
+    let promotion = chosen.promotion.map_or(0, |piece| {
+        bitboard::PIECES
+            .iter()
+            .position(|known| *known == piece)
+            .unwrap_or(0)
+    });
+    (bitboard::square_of(chosen.from) | bitboard::square_of(chosen.to) << 6 | promotion << 12)
+        as u16
+}
+
+fn unpack_move(bits: u16) -> Option<Move> {
+    if bits == 0 {
+        return None;
+    }
+    let bits = bits as usize;
+    let from = bitboard::coords_of(bits & 63);
+    let to = bitboard::coords_of((bits >> 6) & 63);
+    Some(match bits >> 12 {
+        0 => Move::new(from, to),
+        kind => Move::promote(from, to, bitboard::PIECES[kind]),
+    })
+}
+
+//mate scores are stored relative to the node so they stay right when the same
+//position is found at a different ply
+fn to_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score + ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score - ply as i32
+    } else {
+        score
+    }
+}
+
+fn from_table(score: i32, ply: usize) -> i32 {
+    if score >= MATE - MAX_PLY as i32 {
+        score - ply as i32
+    } else if score <= -MATE + MAX_PLY as i32 {
+        score + ply as i32
+    } else {
+        score
+    }
+}
+
+//what each side has on an array board, from the side to move's point of view
+fn material(board: &Matrix) -> i32 {
+    let mut score = 0;
+    for row in 0..board.rows() {
+        for column in 0..board.columns() {
+            let (piece, colour) = board.piece_at((row, column));
+            if piece == Piece::Null {
+                continue;
+            }
+            let value = piece.rules().value();
+            score += if colour == board.turn { value } else { -value };
+        }
+    }
+    //crazyhouse pieces waiting to be dropped are worth as much as on the board
+    for (side, pocket) in board.pockets.iter().enumerate() {
+        let sign = if side == (board.turn == Colour::Black) as usize {
+            1
+        } else {
+            -1
+        };
+        for (index, count) in pocket.iter().enumerate() {
+            score += sign * *count as i32 * POCKET[index].rules().value();
+        }
+    }
+    //in antichess the side with less left is the one doing well
+    if board.variant.rules == Rules::Antichess {
+        return -score;
+    }
+    score
+}
+
+//material and piece squares, from the side to move's point of view
+pub fn evaluate(position: &Position) -> i32 {
+    let mut score = 0;
+    for kind in [PAWN, KNIGHT, BISHOP, ROOK, QUEEN, KING] {
+        for side in 0..2 {
+            let sign = if side == WHITE { 1 } else { -1 };
+            let mut mask = position.mask(kind, side);
+            while mask != 0 {
+                let square = mask.trailing_zeros() as usize;
+                mask &= mask - 1;
+                let (row, column) = (square / 8, square % 8);
+                let index = if side == WHITE {
+                    (7 - row) * 8 + column
+                } else {
+                    row * 8 + column
+                };
+                score += sign * (PIECE_VALUES[kind] + PIECE_SQUARES[kind][index]);
+            }
+        }
+    }
+    if position.turn() == WHITE {
+        score
+    } else {
+        -score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Matrix;
+
+    fn best(fen: &str, depth: i32) -> Report {
+        let position = Position::from(&Matrix::from_fen(fen).unwrap());
+        Search::new().search(&position, &[], depth, Duration::from_secs(60))
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        let report = best("6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1", 3);
+        assert_eq!(report.best.unwrap().name(), "a1a8");
+        assert_eq!(report.score, MATE - 1);
+    }
+
+    #[test]
+    fn takes_a_hanging_queen() {
+        let report = best("4k3/8/8/3q4/8/8/3R4/4K3 w - - 0 1", 3);
+        assert_eq!(report.best.unwrap().name(), "d2d5");
+    }
+
+    #[test]
+    fn table_moves_pack_into_16_bits() {
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+        for fen in fens {
+            for candidate in Matrix::from_fen(fen).unwrap().legal_moves() {
+                assert_eq!(unpack_move(pack_move(candidate)), Some(candidate));
+            }
+        }
+        assert_eq!(std::mem::size_of::<Option<Entry>>(), 12);
+    }
+
+    #[test]
+    fn sees_the_recapture_in_quiescence() {
+        //the knight is guarded, taking it with the queen loses the queen
+        let report = best("4k3/8/2p5/3n4/8/8/8/3QK3 w - - 0 1", 1);
+        assert_ne!(report.best.unwrap().name(), "d1d5");
+    }
+}
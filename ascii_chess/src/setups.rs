@@ -0,0 +1,62 @@
+use crate::variants::{self, Variant};
+
+//a game that doesn't start from the usual position: horde, or an odds game
+//where the stronger player gives up some material so the game is even
+pub struct Setup {
+    pub title: &'static str,
+    pub variant: &'static Variant,
+    pub removed: &'static [(usize, usize)], //taken off the variant's start
+}
+
+pub const ALL: [Setup; 4] = [
+    Setup {
+        title: "Horde: 36 white pawns against the usual army",
+        variant: &variants::HORDE,
+        removed: &[],
+    },
+    //the odds are given by white, who moves first as usual
+    Setup {
+        title: "Queen odds: white without the queen",
+        variant: &variants::STANDARD,
+        removed: &[(0, 3)],
+    },
+    Setup {
+        title: "Knight odds: white without the b1 knight",
+        variant: &variants::STANDARD,
+        removed: &[(0, 1)],
+    },
+    //except here, where black gives the pawn and white still has the move
+    Setup {
+        title: "Pawn and move: black without the f7 pawn",
+        variant: &variants::STANDARD,
+        removed: &[(6, 5)],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::Matrix;
+
+    #[test]
+    fn setups_start_where_they_should() {
+        let fens: Vec<String> = ALL
+            .iter()
+            .map(|setup| {
+                let mut board = Matrix::empty(setup.variant);
+                board.populate_pieces();
+                board.remove_pieces(setup.removed);
+                board.to_fen()
+            })
+            .collect();
+        assert_eq!(
+            fens,
+            [
+                "rnbqkbnr/pppppppp/8/1PP2PP1/PPPPPPPP/PPPPPPPP/PPPPPPPP/PPPPPPPP w kq - 0 1",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNB1KBNR w KQkq - 0 1",
+                "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/R1BQKBNR w KQkq - 0 1",
+                "rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            ]
+        );
+    }
+}
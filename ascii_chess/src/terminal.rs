@@ -0,0 +1,216 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{DisableMouseCapture, EnableMouseCapture};
+use crossterm::style::{
+    Attribute, Color, Print, SetAttribute, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{
+    self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use std::io::{self, stdout, Stdout, Write};
+use std::panic;
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub struct Style {
+    pub foreground: Option<Color>,
+    pub background: Option<Color>,
+    pub bold: bool,
+}
+
+impl Style {
+    pub fn fg(colour: Color) -> Style {
+        Style {
+            foreground: Some(colour),
+            ..Style::default()
+        }
+    }
+}
+
+//one column of the terminal. a glyph wider than one column is stored in its
+//first cell and the cells it covers are left with empty text
+#[derive(Clone, PartialEq)]
+struct Cell {
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    fn blank() -> Cell {
+        Cell {
+            text: String::from(" "),
+            style: Style::default(),
+        }
+    }
+}
+
+//everything that should be on screen for one frame, drawn into before being
+//handed to Screen::draw. anything written outside of the frame is dropped
+pub struct Frame {
+    pub width: u16,
+    pub height: u16,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    pub fn new(width: u16, height: u16) -> Frame {
+        Frame {
+            width,
+            height,
+            cells: vec![Cell::blank(); width as usize * height as usize],
+        }
+    }
+
+    //writes text one column per character, stopping at the right edge
+    pub fn print(&mut self, x: u16, y: u16, text: &str, style: Style) {
+        for (offset, letter) in text.chars().enumerate() {
+            self.put(x + offset as u16, y, letter.to_string(), 1, style);
+        }
+    }
+
+    //writes text that takes up exactly `width` columns, like a square of the board
+    pub fn print_wide(&mut self, x: u16, y: u16, text: &str, width: u16, style: Style) {
+        self.put(x, y, String::from(text), width, style);
+    }
+
+    //what was written to one column, empty for the columns a wide glyph covers
+    #[cfg(test)]
+    pub fn text_at(&self, x: u16, y: u16) -> &str {
+        &self.cells[y as usize * self.width as usize + x as usize].text
+    }
+
+    fn put(&mut self, x: u16, y: u16, text: String, width: u16, style: Style) {
+        if y >= self.height || x + width > self.width {
+            return;
+        }
+        let start = y as usize * self.width as usize + x as usize;
+        self.cells[start] = Cell { text, style };
+        for covered in 1..width as usize {
+            self.cells[start + covered] = Cell {
+                text: String::new(),
+                style,
+            };
+        }
+    }
+}
+
+//owns the terminal while the game runs: raw mode, the alternate screen and
+//mouse capture are switched on when it is opened and always switched back off
+//when it is dropped or the game panics
+pub struct Screen {
+    out: Stdout,
+    previous: Option<Frame>,
+}
+
+impl Screen {
+    pub fn open() -> io::Result<Screen> {
+        let default_hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            restore();
+            default_hook(info);
+        }));
+
+        enable_raw_mode()?;
+        let mut out = stdout();
+        execute!(out, EnterAlternateScreen, Hide)?;
+        //mouse capture isn't supported everywhere, the keyboard still works without it
+        let _ = execute!(out, EnableMouseCapture);
+        Ok(Screen {
+            out,
+            previous: None,
+        })
+    }
+
+    //a frame the size of the terminal right now
+    pub fn frame(&self) -> Frame {
+        let (width, height) = terminal::size().unwrap_or((80, 24));
+        Frame::new(width, height)
+    }
+
+    //forget what is on screen so the next draw repaints everything, e.g. after a resize
+    pub fn invalidate(&mut self) {
+        self.previous = None;
+    }
+
+    //rings the terminal bell, the terminal decides if that is a sound or a flash
+    pub fn bell(&mut self) {
+        let _ = execute!(self.out, Print("\x07"));
+    }
+
+    //only the cells that changed since the last frame are sent to the terminal
+    pub fn draw(&mut self, frame: Frame) -> io::Result<()> {
+        let repaint = match &self.previous {
+            Some(previous) => previous.width != frame.width || previous.height != frame.height,
+            None => true,
+        };
+        if repaint {
+            queue!(
+                self.out,
+                SetAttribute(Attribute::Reset),
+                Clear(ClearType::All)
+            )?;
+        }
+
+        let mut style: Option<Style> = None;
+        let mut cursor: Option<(u16, u16)> = None;
+        for (index, cell) in frame.cells.iter().enumerate() {
+            if cell.text.is_empty() {
+                continue;
+            }
+            if !repaint {
+                if let Some(previous) = &self.previous {
+                    if previous.cells[index] == *cell {
+                        continue;
+                    }
+                }
+            }
+            let x = (index % frame.width as usize) as u16;
+            let y = (index / frame.width as usize) as u16;
+            if cursor != Some((x, y)) {
+                queue!(self.out, MoveTo(x, y))?;
+            }
+            if style != Some(cell.style) {
+                queue!(
+                    self.out,
+                    SetAttribute(Attribute::Reset),
+                    SetForegroundColor(cell.style.foreground.unwrap_or(Color::Reset)),
+                    SetBackgroundColor(cell.style.background.unwrap_or(Color::Reset)),
+                )?;
+                if cell.style.bold {
+                    queue!(self.out, SetAttribute(Attribute::Bold))?;
+                }
+                style = Some(cell.style);
+            }
+            queue!(self.out, Print(&cell.text))?;
+            //wide glyphs are followed by their covered cells, which are never printed
+            let covered = frame.cells[index + 1..]
+                .iter()
+                .take_while(|next| next.text.is_empty())
+                .count() as u16;
+            cursor = Some((x + 1 + covered, y));
+        }
+        queue!(self.out, SetAttribute(Attribute::Reset))?;
+        self.out.flush()?;
+        self.previous = Some(frame);
+        Ok(())
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        restore();
+    }
+}
+
+//undoes everything Screen::open did, safe to call more than once
+fn restore() {
+    let mut out = stdout();
+    let _ = execute!(
+        out,
+        SetAttribute(Attribute::Reset),
+        DisableMouseCapture,
+        Show,
+        LeaveAlternateScreen
+    );
+    let _ = disable_raw_mode();
+}
@@ -0,0 +1,493 @@
+use crate::board::{Colour, Piece};
+use crate::config::{self, Settings};
+use crate::terminal::Style;
+use crossterm::style::Color;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+//how many terminal columns one square of the board takes up
+pub const CELL_WIDTH: u16 = 2;
+
+//a piece set decides what text is drawn for every piece and empty square.
+//every string returned should take up exactly CELL_WIDTH terminal columns
+pub trait PieceSet {
+    fn name(&self) -> &'static str;
+    fn piece(&self, piece: Piece, colour: Colour) -> &'static str;
+    fn square(&self, shade: Colour) -> &'static str;
+    //a square hidden in fog of war
+    fn fog(&self) -> &'static str;
+}
+
+//the original chess glyphs, needs a font and terminal with unicode support
+pub struct UnicodePieces;
+
+//plain letters, white pieces are uppercase and black pieces lowercase
+pub struct AsciiPieces;
+
+impl PieceSet for UnicodePieces {
+    fn name(&self) -> &'static str {
+        "unicode"
+    }
+
+    fn piece(&self, piece: Piece, colour: Colour) -> &'static str {
+        let glyphs = match piece {
+            Piece::Pawn => ["♙ ", "♟ "],
+            Piece::Rook => ["♖ ", "♜ "],
+            Piece::Bishop => ["♗ ", "♝ "],
+            Piece::Knight => ["♘ ", "♞ "],
+            Piece::Queen => ["♕ ", "♛ "],
+            Piece::King => ["♔ ", "♚ "],
+            Piece::Null => return self.square(colour),
+            //few fonts have glyphs for fairy pieces, they are drawn as letters
+            _ => return AsciiPieces.piece(piece, colour),
+        };
+        glyphs[(colour == Colour::Black) as usize]
+    }
+
+    fn square(&self, shade: Colour) -> &'static str {
+        if shade == Colour::Black {
+            "⬛"
+        } else {
+            "⬜"
+        }
+    }
+
+    fn fog(&self) -> &'static str {
+        "░░"
+    }
+}
+
+impl PieceSet for AsciiPieces {
+    fn name(&self) -> &'static str {
+        "ascii"
+    }
+
+    fn piece(&self, piece: Piece, colour: Colour) -> &'static str {
+        let letters = match piece {
+            Piece::Pawn => ["P ", "p "],
+            Piece::Rook => ["R ", "r "],
+            Piece::Bishop => ["B ", "b "],
+            Piece::Knight => ["N ", "n "],
+            Piece::Queen => ["Q ", "q "],
+            Piece::King => ["K ", "k "],
+            Piece::Archbishop => ["A ", "a "],
+            Piece::Chancellor => ["C ", "c "],
+            Piece::Amazon => ["M ", "m "],
+            Piece::Camel => ["L ", "l "],
+            Piece::Nightrider => ["I ", "i "],
+            Piece::Null => return self.square(colour),
+        };
+        letters[(colour == Colour::Black) as usize]
+    }
+
+    fn square(&self, shade: Colour) -> &'static str {
+        if shade == Colour::Black {
+            "# "
+        } else {
+            ". "
+        }
+    }
+
+    fn fog(&self) -> &'static str {
+        "~~"
+    }
+}
+
+//colours used to paint the board, stored as rgb and downsampled when the
+//terminal can't do truecolor. a palette with no colours is the old plain look
+#[derive(Clone)]
+pub struct Palette {
+    pub name: String,
+    colours: Option<Colours>,
+}
+
+#[derive(Clone, Copy)]
+struct Colours {
+    light: (u8, u8, u8),
+    dark: (u8, u8, u8),
+    white_piece: (u8, u8, u8),
+    black_piece: (u8, u8, u8),
+    cursor: (u8, u8, u8),
+}
+
+impl Palette {
+    fn plain() -> Palette {
+        Palette {
+            name: String::from("plain"),
+            colours: None,
+        }
+    }
+
+    fn built_in(name: &str, colours: Colours) -> Palette {
+        Palette {
+            name: String::from(name),
+            colours: Some(colours),
+        }
+    }
+
+    //reads a user theme, one `key = value` per line with colours written as
+    //#rrggbb or r,g,b. lines starting with # are comments
+    pub fn from_file(path: &Path) -> Result<Palette, String> {
+        let text =
+            fs::read_to_string(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Palette::parse(&text, path)
+    }
+
+    //the theme in the text of a theme file, named after the file unless it says
+    //otherwise
+    fn parse(text: &str, path: &Path) -> Result<Palette, String> {
+        let mut name = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let keys = ["light", "dark", "white_piece", "black_piece", "cursor"];
+        let mut values: [Option<(u8, u8, u8)>; 5] = [None; 5];
+
+        for (number, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: &str| format!("{}:{}: {}", path.display(), number + 1, message);
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| error("expected `key = value`"))?;
+            let (key, value) = (key.trim(), value.trim());
+            if key == "name" {
+                name = String::from(value);
+                continue;
+            }
+            let slot = keys
+                .iter()
+                .position(|known| *known == key)
+                .ok_or_else(|| error(&format!("unknown key `{}`", key)))?;
+            values[slot] = Some(parse_colour(value).ok_or_else(|| {
+                error(&format!(
+                    "`{}` is not a colour, use #rrggbb or r,g,b",
+                    value
+                ))
+            })?);
+        }
+
+        if let Some((key, _)) = keys.iter().zip(values).find(|(_, value)| value.is_none()) {
+            return Err(format!("{}: missing `{}`", path.display(), key));
+        }
+        let [light, dark, white_piece, black_piece, cursor] = values.map(Option::unwrap);
+        Ok(Palette::built_in(
+            &name,
+            Colours {
+                light,
+                dark,
+                white_piece,
+                black_piece,
+                cursor,
+            },
+        ))
+    }
+}
+
+fn parse_colour(value: &str) -> Option<(u8, u8, u8)> {
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 {
+            return None;
+        }
+        let channel = |index: usize| u8::from_str_radix(hex.get(index..index + 2)?, 16).ok();
+        return Some((channel(0)?, channel(2)?, channel(4)?));
+    }
+    let mut channels = value.split(',').map(|part| part.trim().parse::<u8>().ok());
+    let colour = (channels.next()??, channels.next()??, channels.next()??);
+    if channels.next().is_some() {
+        return None;
+    }
+    Some(colour)
+}
+
+fn built_in_palettes() -> Vec<Palette> {
+    vec![
+        Palette::built_in(
+            "classic",
+            Colours {
+                light: (205, 170, 125),
+                dark: (139, 100, 66),
+                white_piece: (255, 255, 255),
+                black_piece: (0, 0, 0),
+                cursor: (215, 205, 70),
+            },
+        ),
+        Palette::built_in(
+            "high-contrast",
+            Colours {
+                light: (255, 255, 255),
+                dark: (0, 0, 0),
+                white_piece: (255, 0, 0),
+                black_piece: (0, 120, 255),
+                cursor: (255, 255, 0),
+            },
+        ),
+        //okabe-ito orange and blue, told apart with every common form of colour blindness
+        Palette::built_in(
+            "colour-blind",
+            Colours {
+                light: (200, 200, 200),
+                dark: (110, 110, 110),
+                white_piece: (230, 159, 0),
+                black_piece: (0, 70, 140),
+                cursor: (240, 228, 66),
+            },
+        ),
+        Palette::plain(),
+    ]
+}
+
+//everything needed to turn a square of the board into coloured text
+pub struct Theme {
+    piece_sets: Vec<Box<dyn PieceSet>>,
+    current_pieces: usize,
+    palettes: Vec<Palette>,
+    current: usize,
+    truecolor: bool,
+}
+
+impl Theme {
+    //text and style for one square, highlighted squares are painted with the cursor colour
+    pub fn cell(
+        &self,
+        piece: Piece,
+        colour: Colour,
+        shade: Colour,
+        highlighted: bool,
+    ) -> (&'static str, Style) {
+        let colours = match self.palettes[self.current].colours {
+            Some(colours) => colours,
+            None => return (self.pieces().piece(piece, colour), Style::default()),
+        };
+        let background = if highlighted {
+            colours.cursor
+        } else if shade == Colour::Black {
+            colours.dark
+        } else {
+            colours.light
+        };
+        let foreground = if colour == Colour::Black {
+            colours.black_piece
+        } else {
+            colours.white_piece
+        };
+        let text = if piece == Piece::Null {
+            "  "
+        } else {
+            self.pieces().piece(piece, colour)
+        };
+        let style = Style {
+            foreground: Some(self.colour(foreground)),
+            background: Some(self.colour(background)),
+            bold: true,
+        };
+        (text, style)
+    }
+
+    //what the rest of a square is filled with when it is drawn bigger than one cell
+    pub fn fill(&self, shade: Colour, highlighted: bool) -> (&'static str, Style) {
+        match self.palettes[self.current].colours {
+            Some(_) => self.cell(Piece::Null, shade, shade, highlighted),
+            None => (self.pieces().square(shade), Style::default()),
+        }
+    }
+
+    //fog of war squares are all the same, the dark colour dimmed
+    pub fn fog(&self, highlighted: bool) -> (&'static str, Style) {
+        let colours = match self.palettes[self.current].colours {
+            Some(colours) => colours,
+            None => return (self.pieces().fog(), Style::default()),
+        };
+        let style = Style {
+            foreground: Some(self.colour(colours.dark)),
+            background: Some(if highlighted {
+                self.colour(colours.cursor)
+            } else {
+                Color::Black
+            }),
+            ..Style::default()
+        };
+        (self.pieces().fog(), style)
+    }
+
+    fn pieces(&self) -> &dyn PieceSet {
+        self.piece_sets[self.current_pieces].as_ref()
+    }
+
+    pub fn pieces_name(&self) -> &str {
+        self.pieces().name()
+    }
+
+    pub fn next_pieces(&mut self) {
+        self.current_pieces = (self.current_pieces + 1) % self.piece_sets.len();
+    }
+
+    pub fn palette_name(&self) -> &str {
+        &self.palettes[self.current].name
+    }
+
+    pub fn next_palette(&mut self) {
+        self.current = (self.current + 1) % self.palettes.len();
+    }
+
+    //returns false if there is no palette with that name
+    pub fn set_palette(&mut self, name: &str) -> bool {
+        match self
+            .palettes
+            .iter()
+            .position(|palette| palette.name == name)
+        {
+            Some(index) => {
+                self.current = index;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn colour(&self, rgb: (u8, u8, u8)) -> Color {
+        if self.truecolor {
+            Color::Rgb {
+                r: rgb.0,
+                g: rgb.1,
+                b: rgb.2,
+            }
+        } else {
+            Color::AnsiValue(to_ansi256(rgb))
+        }
+    }
+}
+
+//nearest colour in the 6x6x6 cube of the 256 colour palette
+fn to_ansi256(rgb: (u8, u8, u8)) -> u8 {
+    let level = |channel: u8| -> u8 {
+        if channel < 48 {
+            0
+        } else if channel < 115 {
+            1
+        } else {
+            (channel - 35) / 40
+        }
+    };
+    16 + 36 * level(rgb.0) + 6 * level(rgb.1) + level(rgb.2)
+}
+
+//picks the piece set from the command line (--ascii / --unicode), then the
+//settings file, otherwise falls back to checking if the locale advertises utf-8
+//support. the palette can be chosen with --theme <name> or in the settings, user
+//themes are read from the config folder. problems with user theme files are
+//returned so they can be shown to the player
+pub fn select(args: &[String], settings: &Settings) -> (Theme, Vec<String>) {
+    let piece_sets: Vec<Box<dyn PieceSet>> = vec![Box::new(UnicodePieces), Box::new(AsciiPieces)];
+    let unicode = if args.iter().any(|arg| arg == "--ascii") {
+        false
+    } else if args.iter().any(|arg| arg == "--unicode") {
+        true
+    } else {
+        match settings.pieces.as_str() {
+            "unicode" => true,
+            "ascii" => false,
+            _ => locale_supports_unicode(),
+        }
+    };
+
+    let mut palettes = built_in_palettes();
+    let mut errors = Vec::new();
+    for path in user_theme_files() {
+        match Palette::from_file(&path) {
+            Ok(palette) => palettes.push(palette),
+            Err(error) => errors.push(error),
+        }
+    }
+
+    let mut theme = Theme {
+        piece_sets,
+        current_pieces: if unicode { 0 } else { 1 },
+        palettes,
+        current: 0,
+        truecolor: supports_truecolor(),
+    };
+    if !supports_colour() {
+        theme.set_palette("plain");
+    } else if !theme.set_palette(&settings.theme) {
+        errors.push(format!("settings: unknown theme `{}`", settings.theme));
+    }
+    if let Some(index) = args.iter().position(|arg| arg == "--theme") {
+        match args.get(index + 1) {
+            Some(name) if theme.set_palette(name) => {}
+            Some(name) => errors.push(format!("unknown theme `{}`", name)),
+            None => errors.push(String::from("--theme needs a theme name")),
+        }
+    }
+    (theme, errors)
+}
+
+//the first locale variable that is set wins, same order libc uses
+fn locale_supports_unicode() -> bool {
+    for var in ["LC_ALL", "LC_CTYPE", "LANG"] {
+        if let Ok(value) = env::var(var) {
+            if value.is_empty() {
+                continue;
+            }
+            let value = value.to_lowercase();
+            return value.contains("utf-8") || value.contains("utf8");
+        }
+    }
+    false
+}
+
+//see https://no-color.org
+fn supports_colour() -> bool {
+    env::var_os("NO_COLOR").is_none() && env::var("TERM").map_or(true, |term| term != "dumb")
+}
+
+fn supports_truecolor() -> bool {
+    env::var("COLORTERM").is_ok_and(|value| value == "truecolor" || value == "24bit")
+}
+
+//*.theme files in the themes folder of the config folder
+fn user_theme_files() -> Vec<PathBuf> {
+    let directory = match config::config_dir() {
+        Some(directory) => directory.join("themes"),
+        None => return Vec::new(),
+    };
+    let mut files: Vec<PathBuf> = match fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == "theme")
+            })
+            .collect(),
+        Err(_) => return Vec::new(),
+    };
+    files.sort();
+    files
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn theme_file_is_read_line_by_line() {
+        let text = "# warm browns\nname = sand\nlight = #f0d9b5\ndark = 181, 136, 99\nwhite_piece = #ffffff\nblack_piece = 0,0,0\ncursor = #ff0000\n";
+        let palette = Palette::parse(text, Path::new("themes/warm.theme")).unwrap();
+        assert_eq!(palette.name, "sand");
+        assert_eq!(
+            palette.colours.map(|colours| colours.dark),
+            Some((181, 136, 99))
+        );
+
+        let error = |text: &str| Palette::parse(text, Path::new("warm.theme")).err().unwrap();
+        assert_eq!(error("glow = #ffffff"), "warm.theme:1: unknown key `glow`");
+        assert_eq!(
+            error("# ok\nlight = #fff"),
+            "warm.theme:2: `#fff` is not a colour, use #rrggbb or r,g,b"
+        );
+        assert_eq!(error("light = #f0d9b5"), "warm.theme: missing `dark`");
+    }
+}
@@ -0,0 +1,233 @@
+use crate::board::Piece;
+
+//what a variant changes about how a game is won and what a capture does, on
+//top of how the pieces move
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Rules {
+    Standard,
+    //a capture blows up the square: the capturing piece, the captured one and
+    //every piece but a pawn around it. kings can't capture, blowing up the other
+    //king wins and kings standing next to each other can't be checked
+    Atomic,
+    //getting the king onto one of the four centre squares wins
+    KingOfTheHill,
+    //giving check for the third time wins
+    ThreeCheck,
+    //captured pieces go into the capturer's pocket and can be dropped back on
+    //any empty square instead of making a move
+    Crazyhouse,
+    //losing chess: taking is compulsory, the king is a piece like any other and
+    //whoever runs out of pieces or moves wins
+    Antichess,
+    //dark chess: each side only sees the squares its pieces stand on or could
+    //move to. there is no check, the king has to be taken to win
+    FogOfWar,
+    //white has a horde of pawns and no king. white wins by mate, black by
+    //taking everything white has
+    Horde,
+}
+
+impl Rules {
+    //how a win by these rules is announced, before the winner
+    pub fn win_reason(&self) -> &'static str {
+        match self {
+            Rules::Standard | Rules::Crazyhouse => "Checkmate",
+            Rules::Atomic => "King blown up",
+            Rules::KingOfTheHill => "King on the hill",
+            Rules::ThreeCheck => "Third check",
+            Rules::Antichess => "No pieces or moves left",
+            Rules::FogOfWar => "King taken",
+            Rules::Horde => "Horde wiped out",
+        }
+    }
+
+    //false when the king can be left in check or taken like any other piece
+    pub fn royal_king(&self) -> bool {
+        !matches!(self, Rules::Antichess | Rules::FogOfWar)
+    }
+}
+
+//a kind of game: how big the board is, how it starts and the rules the pawns
+//and kings follow. the same back row goes on both sides, with the pawns in
+//front of it
+pub struct Variant {
+    pub name: &'static str,
+    pub title: &'static str, //how the menu and the side panel call it
+    pub tag: &'static str,   //the Variant tag in PGN files, as lichess writes it
+    pub rows: usize,
+    pub columns: usize,
+    pub back_rank: &'static [Piece],
+    pub double_step: bool, //pawns may move two squares from their starting row
+    pub promotions: &'static [Piece],
+    pub castling: bool,
+    //only the usual game fits in the 8x8 bitboards, everything else goes
+    //through the array move generator
+    pub bitboards: bool,
+    pub rules: Rules,
+}
+
+const STANDARD_BACK_RANK: [Piece; 8] = [
+    Piece::Rook,
+    Piece::Knight,
+    Piece::Bishop,
+    Piece::Queen,
+    Piece::King,
+    Piece::Bishop,
+    Piece::Knight,
+    Piece::Rook,
+];
+const STANDARD_PROMOTIONS: [Piece; 4] = [Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight];
+
+pub const STANDARD: Variant = Variant {
+    name: "standard",
+    title: "Standard",
+    tag: "Standard",
+    rows: 8,
+    columns: 8,
+    back_rank: &STANDARD_BACK_RANK,
+    double_step: true,
+    promotions: &STANDARD_PROMOTIONS,
+    castling: true,
+    bitboards: true,
+    rules: Rules::Standard,
+};
+
+//the usual board and pieces with other ways to win, played on the array board
+const fn on_standard_board(name: &'static str, title: &'static str, rules: Rules) -> Variant {
+    Variant {
+        name,
+        title,
+        tag: title,
+        bitboards: false,
+        rules,
+        ..STANDARD
+    }
+}
+
+pub const ATOMIC: Variant = on_standard_board("atomic", "Atomic", Rules::Atomic);
+pub const KING_OF_THE_HILL: Variant =
+    on_standard_board("kingofthehill", "King of the Hill", Rules::KingOfTheHill);
+pub const THREE_CHECK: Variant = on_standard_board("threecheck", "Three-check", Rules::ThreeCheck);
+pub const CRAZYHOUSE: Variant = on_standard_board("crazyhouse", "Crazyhouse", Rules::Crazyhouse);
+//no castling, and pawns may promote to a king too
+pub const ANTICHESS: Variant = Variant {
+    promotions: &[
+        Piece::Queen,
+        Piece::Rook,
+        Piece::Bishop,
+        Piece::Knight,
+        Piece::King,
+    ],
+    castling: false,
+    ..on_standard_board("antichess", "Antichess", Rules::Antichess)
+};
+pub const FOG_OF_WAR: Variant = Variant {
+    tag: "Fog of War",
+    ..on_standard_board("fogofwar", "Fog of war", Rules::FogOfWar)
+};
+
+//10x8 with an archbishop and a chancellor beside the bishops. the king castles
+//three squares towards the j file or two towards the a file
+pub const CAPABLANCA: Variant = Variant {
+    name: "capablanca",
+    title: "Capablanca 10x8",
+    tag: "Capablanca",
+    rows: 8,
+    columns: 10,
+    back_rank: &[
+        Piece::Rook,
+        Piece::Knight,
+        Piece::Archbishop,
+        Piece::Bishop,
+        Piece::Queen,
+        Piece::King,
+        Piece::Bishop,
+        Piece::Chancellor,
+        Piece::Knight,
+        Piece::Rook,
+    ],
+    double_step: true,
+    promotions: &[
+        Piece::Queen,
+        Piece::Chancellor,
+        Piece::Archbishop,
+        Piece::Rook,
+        Piece::Bishop,
+        Piece::Knight,
+    ],
+    castling: true,
+    bitboards: false,
+    rules: Rules::Standard,
+};
+
+//6x6 without bishops, the first chess program played it in 1956
+pub const LOS_ALAMOS: Variant = Variant {
+    name: "los-alamos",
+    title: "Los Alamos 6x6",
+    tag: "Los Alamos",
+    rows: 6,
+    columns: 6,
+    back_rank: &[
+        Piece::Rook,
+        Piece::Knight,
+        Piece::Queen,
+        Piece::King,
+        Piece::Knight,
+        Piece::Rook,
+    ],
+    double_step: false,
+    promotions: &[Piece::Queen, Piece::Rook, Piece::Knight],
+    castling: false,
+    bitboards: false,
+    rules: Rules::Standard,
+};
+
+//5x5 Gardner minichess, one of each piece
+pub const GARDNER: Variant = Variant {
+    name: "gardner",
+    title: "Gardner 5x5 minichess",
+    tag: "Gardner",
+    rows: 5,
+    columns: 5,
+    back_rank: &[
+        Piece::Rook,
+        Piece::Knight,
+        Piece::Bishop,
+        Piece::Queen,
+        Piece::King,
+    ],
+    double_step: false,
+    promotions: &STANDARD_PROMOTIONS,
+    castling: false,
+    bitboards: false,
+    rules: Rules::Standard,
+};
+
+//36 pawns against the usual army, see Matrix::populate_horde
+pub const HORDE: Variant = on_standard_board("horde", "Horde", Rules::Horde);
+
+pub const ALL: [&Variant; 11] = [
+    &STANDARD,
+    &ATOMIC,
+    &KING_OF_THE_HILL,
+    &THREE_CHECK,
+    &CRAZYHOUSE,
+    &ANTICHESS,
+    &FOG_OF_WAR,
+    &HORDE,
+    &CAPABLANCA,
+    &LOS_ALAMOS,
+    &GARDNER,
+];
+
+//as written in saved games
+pub fn by_name(name: &str) -> Option<&'static Variant> {
+    ALL.into_iter().find(|variant| variant.name == name)
+}
+
+//a FEN only says how big the board is, the first variant of that size is taken.
+//the ones on 8x8 with other rules have to be named, in a saved game's variant line
+pub fn by_size(rows: usize, columns: usize) -> Option<&'static Variant> {
+    ALL.into_iter()
+        .find(|variant| variant.rows == rows && variant.columns == columns)
+}